@@ -0,0 +1,140 @@
+//! Measures the executor's own cost per poll, using tasks which do nothing but
+//! wake themselves and yield. The same tasks are run on a copy of the original
+//! executor, which sent `Arc<Mutex<..>>` tasks through an `async_channel`, for
+//! comparison.
+//!
+//! Run with `cargo bench -p single-thread-executor`.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use single_thread_executor::new_executor_and_spawner;
+
+const TASKS: usize = 100;
+const YIELDS_PER_TASK: usize = 1_000;
+const ROUNDS: usize = 10;
+const POLLS: usize = TASKS * (YIELDS_PER_TASK + 1);
+
+/// Wakes itself and returns `Pending` a fixed number of times.
+struct YieldTimes(usize);
+
+impl Future for YieldTimes {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		if self.0 == 0 {
+			return Poll::Ready(());
+		}
+		self.0 -= 1;
+		cx.waker().wake_by_ref();
+		Poll::Pending
+	}
+}
+
+/// The executor as it was before tasks moved into `Rc` slots, with
+/// `Executor::run`'s loop made synchronous. The benchmark's futures are
+/// `Send`, so it doesn't need the old `unsafe impl Send` wrapper.
+mod channel_executor {
+	use std::future::Future;
+	use std::pin::Pin;
+	use std::sync::{Arc, Mutex};
+	use std::task::Context;
+
+	use async_channel::{Receiver, Sender};
+	use futures::task::{waker_ref, ArcWake};
+
+	type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+	pub struct Executor {
+		ready_queue: Receiver<Arc<Task>>,
+	}
+
+	pub struct Spawner {
+		task_sender: Sender<Arc<Task>>,
+	}
+
+	struct Task {
+		future: Mutex<Option<BoxFuture>>,
+		task_sender: Sender<Arc<Task>>,
+	}
+
+	pub fn new_executor_and_spawner() -> (Executor, Spawner) {
+		const MAX_QUEUED_TASKS: usize = 10_000;
+		let (task_sender, ready_queue) = async_channel::bounded(MAX_QUEUED_TASKS);
+		(Executor { ready_queue }, Spawner { task_sender })
+	}
+
+	impl Spawner {
+		pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+			let task = Arc::new(Task {
+				future: Mutex::new(Some(Box::pin(future))),
+				task_sender: self.task_sender.clone(),
+			});
+			self.task_sender.try_send(task).expect("too many tasks queued");
+		}
+	}
+
+	impl ArcWake for Task {
+		fn wake_by_ref(arc_self: &Arc<Self>) {
+			let cloned = arc_self.clone();
+			arc_self.task_sender.try_send(cloned).expect("too many tasks queued");
+		}
+	}
+
+	impl Executor {
+		pub fn run_until_stalled(&self) -> usize {
+			let mut polls = 0;
+			while let Ok(task) = self.ready_queue.try_recv() {
+				let mut future_slot = task.future.lock().unwrap();
+				if let Some(mut future) = future_slot.take() {
+					let waker = waker_ref(&task);
+					let context = &mut Context::from_waker(&waker);
+					if future.as_mut().poll(context).is_pending() {
+						*future_slot = Some(future);
+					}
+				}
+				polls += 1;
+			}
+			polls
+		}
+	}
+}
+
+/// Runs `round` `ROUNDS` times, returning the best time per poll. `round`
+/// spawns the tasks, then returns a closure which polls them until done.
+fn best_nanos_per_poll<R: FnOnce() -> usize>(round: impl Fn() -> R) -> f64 {
+	let mut best_nanos_per_poll = f64::MAX;
+	for _ in 0..ROUNDS {
+		let run = round();
+		let start = Instant::now();
+		let polls = run();
+		let elapsed = start.elapsed();
+
+		assert_eq!(polls, POLLS);
+		best_nanos_per_poll = best_nanos_per_poll.min(elapsed.as_nanos() as f64 / polls as f64);
+	}
+	best_nanos_per_poll
+}
+
+fn main() {
+	let channel = best_nanos_per_poll(|| {
+		let (executor, spawner) = channel_executor::new_executor_and_spawner();
+		for _ in 0..TASKS {
+			spawner.spawn(YieldTimes(YIELDS_PER_TASK));
+		}
+		move || executor.run_until_stalled()
+	});
+	let current = best_nanos_per_poll(|| {
+		let (executor, spawner) = new_executor_and_spawner();
+		for _ in 0..TASKS {
+			spawner.spawn(YieldTimes(YIELDS_PER_TASK));
+		}
+		move || executor.run_until_stalled()
+	});
+
+	println!("poll overhead, best of {ROUNDS} rounds:");
+	println!("  original channel executor: {channel:.1} ns/poll");
+	println!("  executor:                  {current:.1} ns/poll");
+}
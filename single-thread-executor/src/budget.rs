@@ -0,0 +1,84 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::timer;
+
+/// How much work `Executor::run` does before handing control back to the
+/// browser, so that a busy executor can't hold up rendering or input events.
+///
+/// Once either limit is reached, `run` yields until the event loop's next
+/// macrotask and starts a new turn from there. A turn also ends whenever the
+/// executor runs out of ready tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TurnBudget {
+	/// The most task polls in one turn.
+	pub max_polls: Option<usize>,
+	/// The longest a turn may last. This is only checked between polls, so a
+	/// single slow poll can still run over.
+	pub max_time: Option<Duration>,
+}
+
+impl TurnBudget {
+	/// Never yields while there are tasks ready to poll.
+	pub const UNLIMITED: Self = Self { max_polls: None, max_time: None };
+}
+
+impl Default for TurnBudget {
+	/// Half a frame at 60Hz, leaving the rest for the browser.
+	fn default() -> Self { Self { max_polls: None, max_time: Some(Duration::from_millis(8)) } }
+}
+
+/// Work done in the current turn.
+pub(crate) struct Turn {
+	started: Duration,
+	polls: usize,
+	/// When the last poll finished, if that poll was timed anyway
+	last_poll_finished: Option<Duration>,
+}
+
+impl Turn {
+	pub(crate) fn start() -> Self {
+		Self { started: timer::now(), polls: 0, last_poll_finished: None }
+	}
+
+	pub(crate) fn record_poll(&mut self, finished: Option<Duration>) {
+		self.polls += 1;
+		self.last_poll_finished = finished;
+	}
+
+	pub(crate) fn is_over(&self, budget: TurnBudget) -> bool {
+		budget.max_polls.is_some_and(|max_polls| self.polls >= max_polls)
+			|| budget.max_time.is_some_and(|max_time| {
+				let now = self.last_poll_finished.unwrap_or_else(timer::now);
+				now.saturating_sub(self.started) >= max_time
+			})
+	}
+}
+
+/// Lets every other ready task have a go before this one continues.
+///
+/// This only yields to other tasks. Handing control back to the browser is
+/// up to `Executor::run`, according to its `TurnBudget`.
+pub fn yield_now() -> YieldNow { YieldNow { yielded: false } }
+
+/// The future returned by `yield_now`.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct YieldNow {
+	yielded: bool,
+}
+
+impl Future for YieldNow {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		if self.yielded {
+			return Poll::Ready(());
+		}
+		// Queues the task again, behind everything that's already ready
+		self.yielded = true;
+		cx.waker().wake_by_ref();
+		Poll::Pending
+	}
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+use std::time::Duration;
+
+/// A snapshot of a live task, as returned by `Spawner::tasks`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskInfo {
+	/// Unique among the executor's tasks, counting up in spawn order.
+	pub id: u64,
+	pub name: Option<String>,
+	pub state: TaskState,
+	pub polls: u64,
+	/// The total time spent inside the task's `poll`, counting only the polls
+	/// made while `Spawner::time_polls` was on.
+	pub poll_time: Duration,
+	/// How many times `Spawner::advance_frame` has been called since the task
+	/// was last polled (or spawned, if it hasn't been polled yet).
+	pub frames_since_poll: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskState {
+	/// Sitting in the ready queue.
+	Queued,
+	/// Being polled right now, i.e. this is the task asking.
+	Running,
+	/// Waiting to be woken.
+	Waiting,
+}
+
+impl fmt::Display for TaskInfo {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.name {
+			Some(name) => write!(f, "\"{name}\" (task {})", self.id)?,
+			None => write!(f, "task {}", self.id)?,
+		}
+		write!(
+			f,
+			": {:?}, {} polls taking {:?}, last polled {} frames ago",
+			self.state, self.polls, self.poll_time, self.frames_since_poll
+		)
+	}
+}
@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+use crate::{Task, TaskPanic};
+
+/// The reason a spawned task did not produce its output.
+#[derive(Clone, Debug)]
+pub enum JoinError {
+	/// The task's future was dropped before it completed, e.g. because the
+	/// executor went away while the task was still pending.
+	Dropped,
+	/// The task panicked while being polled.
+	Panicked(TaskPanic),
+	/// The task was stopped through an `AbortHandle`, or by dropping its
+	/// `JoinHandle`.
+	Cancelled,
+}
+
+impl fmt::Display for JoinError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			JoinError::Dropped => write!(f, "task was dropped before completing"),
+			JoinError::Panicked(panic) => write!(f, "task panicked: {panic}"),
+			JoinError::Cancelled => write!(f, "task was cancelled"),
+		}
+	}
+}
+
+impl std::error::Error for JoinError {}
+
+impl PartialEq for JoinError {
+	fn eq(&self, other: &Self) -> bool {
+		match (self, other) {
+			(JoinError::Panicked(a), JoinError::Panicked(b)) => a.message() == b.message(),
+			_ => std::mem::discriminant(self) == std::mem::discriminant(other),
+		}
+	}
+}
+
+impl Eq for JoinError {}
+
+/// Why a task stopped without finishing, if it did. This is recorded by the
+/// task itself rather than its `Completer`, since a panic can drop the
+/// `Completer` before the executor has caught it.
+pub(crate) type Failure = Rc<RefCell<Option<JoinError>>>;
+
+struct JoinState<T> {
+	output: Option<T>,
+	finished: bool,
+	waker: Option<Waker>,
+}
+
+/// An owned handle to a spawned task, which can be awaited to get the task's
+/// output.
+///
+/// Dropping the handle cancels the task, unless it has been `detach`ed first.
+#[must_use = "dropping a JoinHandle cancels the task"]
+pub struct JoinHandle<T> {
+	state: Rc<RefCell<JoinState<T>>>,
+	failure: Failure,
+	abort_handle: Option<AbortHandle>,
+}
+
+/// A cloneable handle which can stop a spawned task from the outside.
+///
+/// Aborting drops the task's future right away, so anything it owns (like a
+/// `FrameGate`) is cleaned up, and its `JoinHandle` resolves to
+/// `JoinError::Cancelled`.
+#[derive(Clone)]
+pub struct AbortHandle {
+	task: Weak<Task>,
+	failure: Failure,
+}
+
+/// The task-side half of a `JoinHandle`. It lives inside the spawned future,
+/// so if that future is torn down before completing, its `Drop` marks the
+/// task as finished without an output.
+pub(crate) struct Completer<T> {
+	state: Rc<RefCell<JoinState<T>>>,
+}
+
+/// Creates the two halves of a join handle. The `JoinHandle` only becomes
+/// usable once `JoinHandle::attach` has tied it to its spawned task.
+pub(crate) fn join_pair<T>() -> (Completer<T>, JoinHandle<T>) {
+	let state = Rc::new(RefCell::new(JoinState { output: None, finished: false, waker: None }));
+	let failure = Failure::default();
+	let abort_handle = AbortHandle { task: Weak::new(), failure: failure.clone() };
+	(
+		Completer { state: state.clone() },
+		JoinHandle { state, failure, abort_handle: Some(abort_handle) },
+	)
+}
+
+impl<T> Completer<T> {
+	pub(crate) fn complete(self, output: T) { self.finish(Some(output)); }
+
+	fn finish(&self, output: Option<T>) {
+		let mut state = self.state.borrow_mut();
+		if !state.finished {
+			state.output = output;
+			state.finished = true;
+			if let Some(waker) = state.waker.take() {
+				waker.wake();
+			}
+		}
+	}
+}
+
+impl<T> Drop for Completer<T> {
+	// Only does anything if the future never got to call `complete`
+	fn drop(&mut self) { self.finish(None); }
+}
+
+impl<T> JoinHandle<T> {
+	pub(crate) fn failure(&self) -> Failure { self.failure.clone() }
+
+	pub(crate) fn attach(&mut self, task: Weak<Task>) {
+		if let Some(abort_handle) = self.abort_handle.as_mut() {
+			abort_handle.task = task;
+		}
+	}
+
+	/// Returns true if the task has finished, successfully or not.
+	pub fn is_finished(&self) -> bool { self.state.borrow().finished }
+
+	/// Cancels the task. Awaiting this handle afterwards yields
+	/// `JoinError::Cancelled`, unless the task had already finished.
+	pub fn abort(&self) {
+		if let Some(abort_handle) = &self.abort_handle {
+			abort_handle.abort();
+		}
+	}
+
+	/// Returns a handle which can cancel the task independently of this one.
+	/// Once this handle has resolved, aborting that does nothing.
+	pub fn abort_handle(&self) -> AbortHandle {
+		self.abort_handle
+			.clone()
+			.unwrap_or_else(|| AbortHandle { task: Weak::new(), failure: self.failure.clone() })
+	}
+
+	/// Drops this handle without cancelling the task, letting it run to
+	/// completion in the background.
+	pub fn detach(mut self) { self.abort_handle = None; }
+}
+
+impl<T> Drop for JoinHandle<T> {
+	fn drop(&mut self) {
+		if let Some(abort_handle) = self.abort_handle.take() {
+			abort_handle.abort();
+		}
+	}
+}
+
+impl AbortHandle {
+	/// Cancels the task if it hasn't finished yet. Does nothing otherwise.
+	pub fn abort(&self) {
+		if let Some(task) = self.task.upgrade() {
+			task.cancel();
+		}
+	}
+
+	/// Returns true if the task was stopped by `abort`.
+	pub fn is_aborted(&self) -> bool {
+		matches!(*self.failure.borrow(), Some(JoinError::Cancelled))
+	}
+}
+
+impl<T> Future for JoinHandle<T> {
+	type Output = Result<T, JoinError>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut state = self.state.borrow_mut();
+		if !state.finished {
+			state.waker = Some(cx.waker().clone());
+			return Poll::Pending;
+		}
+
+		let output = match state.output.take() {
+			Some(output) => Ok(output),
+			None => Err(self.failure.borrow().clone().unwrap_or(JoinError::Dropped)),
+		};
+		drop(state);
+		// The task is over, so there is nothing left to cancel on drop
+		self.abort_handle = None;
+		Poll::Ready(output)
+	}
+}
@@ -1,78 +1,1001 @@
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::Context;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::rc::{Rc, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
 
-use async_channel::{Receiver, Sender};
-use futures::task::{waker_ref, ArcWake};
+use budget::Turn;
+pub use budget::{yield_now, TurnBudget, YieldNow};
+#[cfg(not(target_arch = "wasm32"))]
+use futures::task::ArcWake;
+pub use introspect::{TaskInfo, TaskState};
+use join_handle::Failure;
+pub use join_handle::{AbortHandle, JoinError, JoinHandle};
+pub use panic::{set_task_panic_hook, take_task_panic_hook, TaskPanic};
+use task::Task;
+pub use task_group::{GroupError, TaskGroup};
 
-/// Because this is a single-thread executor, we don't have to be as worried
-/// about Send requirements for the Futures we're executing. Here, we wrap our
-/// non-Send Futures in a type which we assert is Send, which lets us use
-/// Sender/Receiver for convenience.
-///
-/// I may be misunderstanding the actual problem with this solution, but it
-/// works for now, so I'm going to stick with it until I understand Rust async
-/// internals better.
-struct ISwearItsFine(Pin<Box<dyn Future<Output = ()> + 'static>>);
-
-unsafe impl Send for ISwearItsFine {}
-
-impl ISwearItsFine {
-	fn from(future: impl Future<Output = ()> + 'static) -> Self { Self(Box::pin(future)) }
-}
+mod budget;
+mod introspect;
+mod join_handle;
+mod panic;
+pub mod sync;
+mod task;
+mod task_group;
+pub mod timer;
 
+/// Polls spawned tasks. Everything here lives on a single thread, so tasks
+/// don't need to be `Send`, and are stored in plain `Rc`/`RefCell` slots.
 pub struct Executor {
-	ready_queue: Receiver<Arc<Task>>,
+	shared: Rc<Shared>,
+	budget: Cell<TurnBudget>,
 }
 
-/// `Spawner` spawns new futures onto the task channel.
+/// `Spawner` spawns new futures onto the executor's ready queue.
 #[derive(Clone)]
 pub struct Spawner {
-	task_sender: Sender<Arc<Task>>,
+	shared: Rc<Shared>,
+}
+
+/// State shared between the `Executor`, its `Spawner`s, and (weakly) its
+/// tasks.
+struct Shared {
+	ready_queue: RefCell<VecDeque<Rc<Task>>>,
+	capacity: QueueCapacity,
+	/// Wakes `Executor::run` when a task is queued while it's idle
+	run_waker: RefCell<Option<Waker>>,
+	/// Set when the `Executor` is dropped; wakeups are ignored from then on
+	closed: Cell<bool>,
+	/// Set by `shutdown`; no new tasks are accepted from then on
+	shut_down: Cell<bool>,
+	/// Every task whose future hasn't finished yet. The executor owns these,
+	/// since their wakers only refer to them by slot.
+	tasks: RefCell<HashMap<u64, Rc<Task>>>,
+	next_task_id: Cell<u64>,
+	/// Counted by `Spawner::advance_frame`
+	frame: Cell<u64>,
+	/// Set by `Spawner::time_polls`
+	time_polls: Cell<bool>,
+}
+
+/// The maximum number of tasks that can be waiting to be polled before new
+/// spawns are refused. Woken tasks are always queued regardless, since
+/// dropping a wakeup would leave that task stuck forever.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueCapacity {
+	Bounded(usize),
+	Unbounded,
 }
 
-/// A future that can reschedule itself to be polled by an `Executor`.
-struct Task {
-	future: Mutex<Option<ISwearItsFine>>,
-	task_sender: Sender<Arc<Task>>,
+impl Default for QueueCapacity {
+	fn default() -> Self { QueueCapacity::Bounded(10_000) }
 }
 
+/// The reason a future could not be spawned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnError {
+	/// The ready queue has reached its `QueueCapacity`.
+	QueueFull,
+	/// The `Executor` has been shut down or dropped, so the task would never
+	/// run.
+	Closed,
+}
+
+impl fmt::Display for SpawnError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SpawnError::QueueFull => write!(f, "too many tasks queued"),
+			SpawnError::Closed => write!(f, "executor is no longer accepting tasks"),
+		}
+	}
+}
+
+impl std::error::Error for SpawnError {}
+
 pub fn new_executor_and_spawner() -> (Executor, Spawner) {
-	const MAX_QUEUED_TASKS: usize = 10_000;
-	let (task_sender, ready_queue) = async_channel::bounded(MAX_QUEUED_TASKS);
-	(Executor { ready_queue }, Spawner { task_sender })
+	new_executor_and_spawner_with_capacity(QueueCapacity::default())
+}
+
+pub fn new_executor_and_spawner_with_capacity(capacity: QueueCapacity) -> (Executor, Spawner) {
+	let shared = Rc::new(Shared {
+		ready_queue: RefCell::new(VecDeque::new()),
+		capacity,
+		run_waker: RefCell::new(None),
+		closed: Cell::new(false),
+		shut_down: Cell::new(false),
+		tasks: RefCell::new(HashMap::new()),
+		next_task_id: Cell::new(0),
+		frame: Cell::new(0),
+		time_polls: Cell::new(false),
+	});
+	(Executor { shared: shared.clone(), budget: Cell::default() }, Spawner { shared })
 }
 
 impl Spawner {
+	/// Spawns a future onto the executor.
+	///
+	/// Panics if the task can't be queued; use `try_spawn` to handle that case.
 	pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
-		let future = ISwearItsFine::from(future);
-		let task = Arc::new(Task {
-			future: Mutex::new(Some(future)),
-			task_sender: self.task_sender.clone(),
-		});
-		self.task_sender.try_send(task).expect("too many tasks queued");
+		self.try_spawn(future).expect("failed to spawn task");
+	}
+
+	/// Spawns a future onto the executor, or returns an error if the ready
+	/// queue is full or the executor is gone.
+	pub fn try_spawn(&self, future: impl Future<Output = ()> + 'static) -> Result<(), SpawnError> {
+		TaskBuilder::new(self, None).try_spawn(future)
+	}
+
+	/// Spawns a future with any output type, returning a handle which can be
+	/// awaited to retrieve that output once the task finishes.
+	///
+	/// Panics if the task can't be queued; use `try_spawn_with_handle` to
+	/// handle that case.
+	pub fn spawn_with_handle<T: 'static>(
+		&self,
+		future: impl Future<Output = T> + 'static,
+	) -> JoinHandle<T> {
+		self.try_spawn_with_handle(future).expect("failed to spawn task")
+	}
+
+	pub fn try_spawn_with_handle<T: 'static>(
+		&self,
+		future: impl Future<Output = T> + 'static,
+	) -> Result<JoinHandle<T>, SpawnError> {
+		TaskBuilder::new(self, None).try_spawn_with_handle(future)
+	}
+
+	/// Returns a builder for spawning a task with a name, which shows up in
+	/// `tasks` and `stalled_tasks`.
+	pub fn named(&self, name: impl Into<String>) -> TaskBuilder<'_> {
+		TaskBuilder::new(self, Some(name.into()))
+	}
+
+	/// Describes every task which hasn't finished yet, in the order they were
+	/// spawned.
+	pub fn tasks(&self) -> Vec<TaskInfo> {
+		let frame = self.shared.frame.get();
+		let mut tasks: Vec<TaskInfo> =
+			self.shared.tasks.borrow().values().map(|task| task.info(frame)).collect();
+		tasks.sort_by_key(|task| task.id);
+		tasks
+	}
+
+	/// Returns the tasks which are waiting to be woken, and haven't been polled
+	/// in more than `frames` frames. A task waiting on a `FrameGate` is polled
+	/// every frame, so one that shows up here is probably stuck.
+	pub fn stalled_tasks(&self, frames: u64) -> Vec<TaskInfo> {
+		let mut tasks = self.tasks();
+		tasks.retain(|task| task.state == TaskState::Waiting && task.frames_since_poll > frames);
+		tasks
+	}
+
+	/// Starts a new frame, as far as `TaskInfo::frames_since_poll` is
+	/// concerned. This is meant to be called once per animation frame.
+	pub fn advance_frame(&self) { self.shared.frame.set(self.shared.frame.get() + 1); }
+
+	/// The number of times `advance_frame` has been called.
+	pub fn frame(&self) -> u64 { self.shared.frame.get() }
+
+	/// Sets whether each poll is timed, adding up to `TaskInfo::poll_time`.
+	/// This is off to begin with, since reading the clock around every poll
+	/// can cost more than the poll itself.
+	pub fn time_polls(&self, enabled: bool) { self.shared.time_polls.set(enabled); }
+
+	/// Stops the executor from accepting new tasks, so `spawn` fails from
+	/// here on, including from within running tasks. Existing tasks are
+	/// drained or cancelled depending on `mode`, after which `run` returns.
+	///
+	/// This can be called again to escalate from `Drain` to `Cancel`.
+	pub fn shutdown(&self, mode: ShutdownMode) { self.shared.shutdown(mode); }
+
+	pub fn is_shut_down(&self) -> bool { self.shared.shut_down.get() }
+
+	fn try_spawn_task(
+		&self,
+		future: impl Future<Output = ()> + 'static,
+		failure: Failure,
+		name: Option<String>,
+	) -> Result<Weak<Task>, SpawnError> {
+		if self.shared.closed.get() || self.shared.shut_down.get() {
+			return Err(SpawnError::Closed);
+		}
+		if let QueueCapacity::Bounded(max_queued_tasks) = self.shared.capacity {
+			if self.shared.ready_queue.borrow().len() >= max_queued_tasks {
+				return Err(SpawnError::QueueFull);
+			}
+		}
+
+		let task = Task::new(future, &self.shared, failure, name);
+		let weak_task = Rc::downgrade(&task);
+		task.schedule();
+		Ok(weak_task)
 	}
 }
 
-impl ArcWake for Task {
-	fn wake_by_ref(arc_self: &Arc<Self>) {
-		let cloned = arc_self.clone();
-		arc_self.task_sender.try_send(cloned).expect("too many tasks queued");
+/// Spawns a task with extra settings, created by `Spawner::named`. Its
+/// methods match the ones on `Spawner`.
+#[must_use = "a TaskBuilder does nothing until one of its spawn methods is called"]
+pub struct TaskBuilder<'a> {
+	spawner: &'a Spawner,
+	name: Option<String>,
+}
+
+impl<'a> TaskBuilder<'a> {
+	fn new(spawner: &'a Spawner, name: Option<String>) -> Self { Self { spawner, name } }
+
+	pub fn spawn(self, future: impl Future<Output = ()> + 'static) {
+		self.try_spawn(future).expect("failed to spawn task");
+	}
+
+	pub fn try_spawn(self, future: impl Future<Output = ()> + 'static) -> Result<(), SpawnError> {
+		self.spawner.try_spawn_task(future, Failure::default(), self.name).map(|_| ())
+	}
+
+	pub fn spawn_with_handle<T: 'static>(
+		self,
+		future: impl Future<Output = T> + 'static,
+	) -> JoinHandle<T> {
+		self.try_spawn_with_handle(future).expect("failed to spawn task")
+	}
+
+	pub fn try_spawn_with_handle<T: 'static>(
+		self,
+		future: impl Future<Output = T> + 'static,
+	) -> Result<JoinHandle<T>, SpawnError> {
+		let (completer, mut handle) = join_handle::join_pair();
+		let failure = handle.failure();
+		let task = self.spawner.try_spawn_task(
+			async move {
+				let output = future.await;
+				completer.complete(output);
+			},
+			failure,
+			self.name,
+		)?;
+		handle.attach(task);
+		Ok(handle)
 	}
 }
 
+impl Shared {
+	/// Queues a task to be polled. This never fails: if the executor is gone,
+	/// nobody is left to poll the task, so the wakeup is simply dropped. Use
+	/// `Task::schedule` rather than calling this directly, so the task is
+	/// only queued once.
+	fn schedule(&self, task: Rc<Task>) {
+		if self.closed.get() {
+			return;
+		}
+		self.ready_queue.borrow_mut().push_back(task);
+		if let Some(waker) = self.run_waker.borrow_mut().take() {
+			waker.wake();
+		}
+	}
+
+	fn next_task(&self) -> Option<Rc<Task>> { self.ready_queue.borrow_mut().pop_front() }
+
+	/// `run` can stop once nothing could add work (because the executor was
+	/// shut down, or no `Spawner` is left), and no task is left to be woken.
+	fn is_finished(self: &Rc<Self>) -> bool {
+		(self.shut_down.get() || Rc::strong_count(self) == 1) && self.tasks.borrow().is_empty()
+	}
+
+	fn shutdown(&self, mode: ShutdownMode) {
+		self.shut_down.set(true);
+
+		if mode == ShutdownMode::Cancel {
+			// Collected first, since cancelling drops futures, which may touch the
+			// executor again
+			let tasks: Vec<Rc<Task>> = self.tasks.borrow().values().cloned().collect();
+			tasks.iter().for_each(|task| task.cancel());
+
+			let queued = std::mem::take(&mut *self.ready_queue.borrow_mut());
+			drop(queued);
+		}
+
+		if let Some(waker) = self.run_waker.borrow_mut().take() {
+			waker.wake();
+		}
+	}
+}
+
+/// What to do with existing tasks when the executor is shut down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownMode {
+	/// Keep polling existing tasks, and let `run` return once they've all
+	/// finished.
+	Drain,
+	/// Cancel every task straight away, so `run` returns as soon as it's next
+	/// polled. Their `JoinHandle`s resolve to `JoinError::Cancelled`.
+	Cancel,
+}
+
 impl Executor {
+	/// Polls tasks as they become ready, yielding to the surrounding event
+	/// loop whenever none are, or the `TurnBudget` runs out. Returns once every
+	/// task has finished, and either `shutdown` has been called or every
+	/// `Spawner` is gone.
 	pub async fn run(&self) {
-		while let Ok(task) = self.ready_queue.recv().await {
-			let mut future_slot = task.future.lock().unwrap();
-			if let Some(mut future) = future_slot.take() {
-				let waker = waker_ref(&task);
-				let context = &mut Context::from_waker(&waker);
-				if future.0.as_mut().poll(context).is_pending() {
-					*future_slot = Some(future);
+		let mut turn = Turn::start();
+		loop {
+			let mut idled = false;
+			let next = poll_fn(|cx| {
+				let next = self.poll_next_task(cx);
+				idled |= next.is_pending();
+				next
+			})
+			.await;
+			let Some(task) = next else {
+				break;
+			};
+			if idled {
+				turn = Turn::start();
+			}
+
+			let finished = task.poll();
+			turn.record_poll(finished);
+			if turn.is_over(self.budget.get()) {
+				timer::yield_to_event_loop().await;
+				turn = Turn::start();
+			}
+		}
+	}
+
+	/// Sets how much `run` may do before yielding to the event loop. This only
+	/// affects `run`; the other ways of polling tasks stop by themselves.
+	pub fn set_budget(&self, budget: TurnBudget) { self.budget.set(budget); }
+
+	pub fn budget(&self) -> TurnBudget { self.budget.get() }
+
+	/// See `Spawner::shutdown`.
+	pub fn shutdown(&self, mode: ShutdownMode) { self.shared.shutdown(mode); }
+
+	/// Polls a single ready task, if there is one, without waiting for more
+	/// work to arrive. Returns true if a task was polled.
+	pub fn poll_once(&self) -> bool {
+		#[cfg(not(target_arch = "wasm32"))]
+		timer::fire_expired();
+
+		match self.shared.next_task() {
+			Some(task) => {
+				task.poll();
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Polls ready tasks until none are left, returning how many polls were
+	/// made. Tasks which are woken while this runs are polled as well.
+	pub fn run_until_stalled(&self) -> usize {
+		let mut polls = 0;
+		while self.poll_once() {
+			polls += 1;
+		}
+		polls
+	}
+
+	/// Drives `future` to completion on the current thread, polling spawned
+	/// tasks whenever it is waiting on them.
+	///
+	/// This parks the thread while nothing is ready, so it is only available on
+	/// native targets, where it's mostly useful for tests. If the only thing
+	/// left to wait for is a timer, the current `timer::Clock` decides how to
+	/// wait for it, which lets a `ManualClock` skip straight to the deadline.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+		let mut future = std::pin::pin!(future);
+		let signal =
+			Arc::new(ThreadSignal { thread: std::thread::current(), woken: AtomicBool::new(true) });
+		let waker = futures::task::waker(signal.clone());
+		let context = &mut Context::from_waker(&waker);
+
+		loop {
+			if signal.woken.swap(false, Ordering::SeqCst) {
+				if let Poll::Ready(output) = future.as_mut().poll(context) {
+					return output;
 				}
 			}
+			if !self.poll_once() && !signal.woken.load(Ordering::SeqCst) && !timer::wait_for_next()
+			{
+				std::thread::park();
+			}
+		}
+	}
+
+	fn poll_next_task(&self, cx: &mut Context<'_>) -> Poll<Option<Rc<Task>>> {
+		if let Some(task) = self.shared.next_task() {
+			return Poll::Ready(Some(task));
+		}
+		if self.shared.is_finished() {
+			return Poll::Ready(None);
+		}
+		*self.shared.run_waker.borrow_mut() = Some(cx.waker().clone());
+		Poll::Pending
+	}
+}
+
+impl Drop for Executor {
+	fn drop(&mut self) {
+		self.shared.closed.set(true);
+		// Taken out first, since dropping a task's future can wake other tasks
+		let queued = std::mem::take(&mut *self.shared.ready_queue.borrow_mut());
+		drop(queued);
+		let tasks = std::mem::take(&mut *self.shared.tasks.borrow_mut());
+		drop(tasks);
+	}
+}
+
+impl Drop for Spawner {
+	fn drop(&mut self) {
+		// Let an idle `run` notice if this was the last way to add work
+		if let Some(waker) = self.shared.run_waker.borrow_mut().take() {
+			waker.wake();
+		}
+	}
+}
+
+/// Wakes the thread blocked in `Executor::block_on` when its future is woken.
+#[cfg(not(target_arch = "wasm32"))]
+struct ThreadSignal {
+	thread: std::thread::Thread,
+	woken: AtomicBool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ArcWake for ThreadSignal {
+	fn wake_by_ref(arc_self: &Arc<Self>) {
+		arc_self.woken.store(true, Ordering::SeqCst);
+		arc_self.thread.unpark();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::{Cell, RefCell};
+	use std::rc::Rc;
+	use std::time::Duration;
+
+	use futures::executor::block_on;
+	use futures::future::{poll_fn, select, Either};
+
+	use super::*;
+
+	type WakerSlot = Rc<RefCell<Option<Waker>>>;
+
+	/// Returns a task which records its waker, then counts its polls until
+	/// the returned sender is closed.
+	fn waker_recording_task(spawner: &Spawner) -> (WakerSlot, Rc<Cell<usize>>, JoinHandle<()>) {
+		let waker_slot = Rc::new(RefCell::new(None));
+		let polls = Rc::new(Cell::new(0));
+		let (task_waker_slot, task_polls) = (waker_slot.clone(), polls.clone());
+		let handle = spawner.spawn_with_handle(poll_fn(move |cx| {
+			task_polls.set(task_polls.get() + 1);
+			*task_waker_slot.borrow_mut() = Some(cx.waker().clone());
+			Poll::<()>::Pending
+		}));
+		(waker_slot, polls, handle)
+	}
+
+	#[test]
+	fn join_handle_yields_output() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let handle = spawner.spawn_with_handle(async { 6 * 7 });
+
+		let output = match block_on(select(Box::pin(executor.run()), handle)) {
+			Either::Right((output, _)) => output,
+			Either::Left(_) => panic!("executor stopped before the task finished"),
+		};
+		assert_eq!(output, Ok(42));
+	}
+
+	#[test]
+	fn join_handle_reports_panic() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let handle = spawner.spawn_with_handle(async { panic!("task failed") });
+
+		match executor.block_on(handle) {
+			Err(JoinError::Panicked(panic)) => assert_eq!(panic.message(), "task failed"),
+			other => panic!("expected a panic, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn panicking_task_does_not_stop_others() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<u32>();
+		let failing = spawner.spawn_with_handle(async move {
+			sender.send(1).await.unwrap();
+			panic!("failed after sending");
+		});
+		let surviving = spawner.spawn_with_handle(async move {
+			let first = receiver.recv().await.unwrap();
+			// The sender is dropped along with the panicking task
+			assert!(receiver.recv().await.is_err());
+			first
+		});
+
+		executor.run_until_stalled();
+		assert!(matches!(executor.block_on(failing), Err(JoinError::Panicked(_))));
+		assert_eq!(executor.block_on(surviving), Ok(1));
+	}
+
+	#[test]
+	fn panic_hook_sees_tasks_without_handles() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let reported = Rc::new(RefCell::new(Vec::new()));
+		let hook_reported = reported.clone();
+		set_task_panic_hook(move |panic| hook_reported.borrow_mut().push(panic.to_string()));
+
+		spawner.spawn(async { panic!("unobserved {}", 42) });
+		executor.run_until_stalled();
+		take_task_panic_hook();
+
+		assert_eq!(*reported.borrow(), vec!["unobserved 42".to_owned()]);
+	}
+
+	#[test]
+	fn try_spawn_reports_full_queue() {
+		let (_executor, spawner) =
+			new_executor_and_spawner_with_capacity(QueueCapacity::Bounded(1));
+		assert_eq!(spawner.try_spawn(async {}), Ok(()));
+		assert_eq!(spawner.try_spawn(async {}), Err(SpawnError::QueueFull));
+	}
+
+	#[test]
+	fn try_spawn_reports_closed_executor() {
+		let (executor, spawner) = new_executor_and_spawner();
+		drop(executor);
+		assert_eq!(spawner.try_spawn(async {}), Err(SpawnError::Closed));
+	}
+
+	#[test]
+	fn wakeups_beyond_capacity_are_not_lost() {
+		let (executor, spawner) = new_executor_and_spawner_with_capacity(QueueCapacity::Bounded(1));
+		let (waker, polls, _handle) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+
+		spawner.spawn(async {});
+		assert_eq!(spawner.try_spawn(async {}), Err(SpawnError::QueueFull));
+		waker.borrow().as_ref().unwrap().wake_by_ref();
+
+		assert_eq!(executor.run_until_stalled(), 2);
+		assert_eq!(polls.get(), 2);
+	}
+
+	#[test]
+	fn poll_once_polls_a_single_task() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let polled = Rc::new(Cell::new(0));
+		for _ in 0..2 {
+			let polled = polled.clone();
+			spawner.spawn(async move { polled.set(polled.get() + 1) });
+		}
+
+		assert!(executor.poll_once());
+		assert_eq!(polled.get(), 1);
+		assert!(executor.poll_once());
+		assert_eq!(polled.get(), 2);
+		assert!(!executor.poll_once());
+	}
+
+	#[test]
+	fn run_until_stalled_leaves_pending_tasks_alone() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<u32>();
+		let handle = spawner.spawn_with_handle(async move { receiver.recv().await.unwrap() });
+
+		assert_eq!(executor.run_until_stalled(), 1);
+		assert!(!handle.is_finished());
+
+		sender.try_send(5).unwrap();
+		assert_eq!(executor.run_until_stalled(), 1);
+		assert!(handle.is_finished());
+	}
+
+	#[test]
+	fn block_on_drives_spawned_tasks() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let first = spawner.spawn_with_handle(async { 1 });
+		let second = spawner.spawn_with_handle(async { 2 });
+
+		let sum = executor.block_on(async { first.await.unwrap() + second.await.unwrap() });
+		assert_eq!(sum, 3);
+	}
+
+	#[test]
+	fn aborted_task_is_never_polled() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let polled = Rc::new(Cell::new(false));
+		let task_polled = polled.clone();
+		let handle = spawner.spawn_with_handle(async move { task_polled.set(true) });
+
+		handle.abort();
+		executor.run_until_stalled();
+		assert!(!polled.get());
+		assert_eq!(executor.block_on(handle), Err(JoinError::Cancelled));
+	}
+
+	#[test]
+	fn abort_drops_pending_future() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<()>();
+		let handle = spawner.spawn_with_handle(async move { receiver.recv().await });
+		executor.run_until_stalled();
+		assert!(!sender.is_closed());
+
+		let abort_handle = handle.abort_handle();
+		abort_handle.abort();
+		assert!(abort_handle.is_aborted());
+		assert!(sender.is_closed());
+		assert_eq!(executor.block_on(handle), Err(JoinError::Cancelled));
+	}
+
+	#[test]
+	fn aborting_a_finished_task_does_nothing() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let mut handle = spawner.spawn_with_handle(async { 7 });
+		executor.run_until_stalled();
+		assert_eq!(block_on(&mut handle), Ok(7));
+
+		let abort_handle = handle.abort_handle();
+		abort_handle.abort();
+		assert!(!abort_handle.is_aborted());
+	}
+
+	#[test]
+	fn task_can_abort_itself() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let abort_slot = Rc::new(RefCell::new(None::<AbortHandle>));
+		let task_abort_slot = abort_slot.clone();
+		let handle = spawner.spawn_with_handle(async move {
+			task_abort_slot.borrow().as_ref().unwrap().abort();
+			futures::future::pending::<()>().await
+		});
+		*abort_slot.borrow_mut() = Some(handle.abort_handle());
+
+		assert_eq!(executor.block_on(handle), Err(JoinError::Cancelled));
+	}
+
+	#[test]
+	fn dropping_join_handle_cancels_task() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let polled = Rc::new(Cell::new(false));
+		let task_polled = polled.clone();
+		drop(spawner.spawn_with_handle(async move { task_polled.set(true) }));
+
+		executor.run_until_stalled();
+		assert!(!polled.get());
+	}
+
+	#[test]
+	fn detached_task_keeps_running() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let polled = Rc::new(Cell::new(false));
+		let task_polled = polled.clone();
+		spawner.spawn_with_handle(async move { task_polled.set(true) }).detach();
+
+		executor.run_until_stalled();
+		assert!(polled.get());
+	}
+
+	#[test]
+	fn dropping_executor_drops_pending_tasks() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let handle = spawner.spawn_with_handle(futures::future::pending::<()>());
+		executor.run_until_stalled();
+
+		drop(executor);
+		assert!(handle.is_finished());
+		assert_eq!(block_on(handle), Err(JoinError::Dropped));
+	}
+
+	#[test]
+	fn run_returns_once_spawners_and_tasks_are_gone() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<()>();
+		spawner.spawn(async move {
+			receiver.recv().await.unwrap();
+		});
+		drop(spawner);
+
+		let run = executor.run();
+		futures::pin_mut!(run);
+		let waker = futures::task::noop_waker();
+		let context = &mut Context::from_waker(&waker);
+		assert!(run.as_mut().poll(context).is_pending());
+
+		sender.try_send(()).unwrap();
+		assert!(run.as_mut().poll(context).is_ready());
+	}
+
+	#[test]
+	fn repeated_wakeups_are_polled_once() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (waker, polls, _handle) = waker_recording_task(&spawner);
+		assert_eq!(executor.run_until_stalled(), 1);
+
+		for _ in 0..5 {
+			waker.borrow().as_ref().unwrap().wake_by_ref();
+		}
+		assert_eq!(executor.run_until_stalled(), 1);
+		assert_eq!(polls.get(), 2);
+	}
+
+	#[test]
+	fn wakeups_from_other_threads_are_refused() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (waker_slot, polls, _handle) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+		let waker = waker_slot.borrow().clone().unwrap();
+
+		// Wakers can still be cloned and dropped anywhere
+		let sent_waker = waker.clone();
+		std::thread::spawn(move || drop(sent_waker.clone())).join().unwrap();
+		let sent_waker = waker.clone();
+		assert!(std::thread::spawn(move || sent_waker.wake()).join().is_err());
+		assert_eq!(executor.run_until_stalled(), 0);
+
+		waker.wake();
+		assert_eq!(executor.run_until_stalled(), 1);
+		assert_eq!(polls.get(), 2);
+	}
+
+	#[test]
+	fn old_wakers_miss_the_next_task_in_their_slot() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (waker_slot, _, handle) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+		let old_waker = waker_slot.borrow().clone().unwrap();
+		drop(handle);
+
+		let (_, polls, _handle) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+		old_waker.wake();
+		assert_eq!(executor.run_until_stalled(), 0);
+		assert_eq!(polls.get(), 1);
+	}
+
+	#[test]
+	fn repeated_wakeups_take_one_queue_slot() {
+		let (executor, spawner) = new_executor_and_spawner_with_capacity(QueueCapacity::Bounded(2));
+		let (waker, polls, _handle) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+
+		for _ in 0..5 {
+			waker.borrow().as_ref().unwrap().wake_by_ref();
+		}
+		assert_eq!(spawner.try_spawn(async {}), Ok(()));
+		assert_eq!(executor.run_until_stalled(), 2);
+		assert_eq!(polls.get(), 2);
+	}
+
+	#[test]
+	fn wakeup_during_poll_queues_task_again() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let mut polls = 0;
+		let _handle = spawner.spawn_with_handle(poll_fn(move |cx| {
+			polls += 1;
+			if polls < 3 {
+				cx.waker().wake_by_ref();
+				cx.waker().wake_by_ref();
+			}
+			Poll::<()>::Pending
+		}));
+
+		assert_eq!(executor.run_until_stalled(), 3);
+	}
+
+	#[test]
+	fn finished_task_is_not_requeued() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let waker_slot = Rc::new(RefCell::new(None::<Waker>));
+		let task_waker_slot = waker_slot.clone();
+		spawner.spawn(poll_fn(move |cx| {
+			*task_waker_slot.borrow_mut() = Some(cx.waker().clone());
+			Poll::Ready(())
+		}));
+		assert_eq!(executor.run_until_stalled(), 1);
+
+		waker_slot.borrow().as_ref().unwrap().wake_by_ref();
+		assert_eq!(executor.run_until_stalled(), 0);
+	}
+
+	#[test]
+	fn shutdown_refuses_new_tasks() {
+		let (executor, spawner) = new_executor_and_spawner();
+		executor.shutdown(ShutdownMode::Drain);
+		assert!(spawner.is_shut_down());
+		assert_eq!(spawner.try_spawn(async {}), Err(SpawnError::Closed));
+	}
+
+	#[test]
+	fn shutdown_cancel_drops_tasks_and_stops_run() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (_sender, receiver) = async_channel::unbounded::<()>();
+		let handle = spawner.spawn_with_handle(async move { receiver.recv().await });
+		executor.run_until_stalled();
+
+		spawner.shutdown(ShutdownMode::Cancel);
+		block_on(executor.run());
+		assert_eq!(block_on(handle), Err(JoinError::Cancelled));
+	}
+
+	#[test]
+	fn shutdown_drain_finishes_existing_tasks() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<u32>();
+		let handle = spawner.spawn_with_handle(async move { receiver.recv().await.unwrap() });
+		executor.run_until_stalled();
+
+		spawner.shutdown(ShutdownMode::Drain);
+		let run = executor.run();
+		futures::pin_mut!(run);
+		let waker = futures::task::noop_waker();
+		let context = &mut Context::from_waker(&waker);
+		assert!(run.as_mut().poll(context).is_pending());
+
+		sender.try_send(3).unwrap();
+		assert!(run.as_mut().poll(context).is_ready());
+		assert_eq!(block_on(handle), Ok(3));
+	}
+
+	#[test]
+	fn tasks_cannot_spawn_while_draining() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let task_spawner = spawner.clone();
+		let handle = spawner.spawn_with_handle(async move { task_spawner.try_spawn(async {}) });
+
+		spawner.shutdown(ShutdownMode::Drain);
+		assert_eq!(executor.block_on(handle), Ok(Err(SpawnError::Closed)));
+	}
+
+	#[test]
+	fn yield_now_lets_other_tasks_run() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let order = Rc::new(RefCell::new(Vec::new()));
+		let first_order = order.clone();
+		spawner.spawn(async move {
+			first_order.borrow_mut().push("first starts");
+			yield_now().await;
+			first_order.borrow_mut().push("first ends");
+		});
+		let second_order = order.clone();
+		spawner.spawn(async move { second_order.borrow_mut().push("second") });
+
+		executor.run_until_stalled();
+		assert_eq!(*order.borrow(), ["first starts", "second", "first ends"]);
+	}
+
+	#[test]
+	fn run_yields_once_poll_budget_is_spent() {
+		let (executor, spawner) = new_executor_and_spawner();
+		executor.set_budget(TurnBudget { max_polls: Some(2), max_time: None });
+		let polled = Rc::new(Cell::new(0));
+		for _ in 0..5 {
+			let polled = polled.clone();
+			spawner.spawn(async move { polled.set(polled.get() + 1) });
+		}
+
+		let run = executor.run();
+		futures::pin_mut!(run);
+		let waker = futures::task::noop_waker();
+		let context = &mut Context::from_waker(&waker);
+		assert!(run.as_mut().poll(context).is_pending());
+		assert_eq!(polled.get(), 2);
+		assert!(run.as_mut().poll(context).is_pending());
+		assert_eq!(polled.get(), 4);
+	}
+
+	#[test]
+	fn run_yields_once_time_budget_is_spent() {
+		let clock = Rc::new(timer::ManualClock::new());
+		timer::set_clock(clock.clone());
+		// Timed polls hand their finishing time on to the turn, rather than it
+		// reading the clock again
+		for time_polls in [false, true] {
+			let (executor, spawner) = new_executor_and_spawner();
+			executor.set_budget(TurnBudget {
+				max_polls: None,
+				max_time: Some(Duration::from_millis(8)),
+			});
+			spawner.time_polls(time_polls);
+			let polled = Rc::new(Cell::new(0));
+			for _ in 0..3 {
+				let (clock, polled) = (clock.clone(), polled.clone());
+				spawner.spawn(async move {
+					clock.advance(Duration::from_millis(5));
+					polled.set(polled.get() + 1);
+				});
+			}
+
+			let run = executor.run();
+			futures::pin_mut!(run);
+			let waker = futures::task::noop_waker();
+			let context = &mut Context::from_waker(&waker);
+			assert!(run.as_mut().poll(context).is_pending());
+			assert_eq!(polled.get(), 2);
+			assert!(run.as_mut().poll(context).is_pending());
+			assert_eq!(polled.get(), 3);
 		}
 	}
+
+	#[test]
+	fn tasks_lists_live_tasks_with_their_state() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (_waker_slot, _polls, waiting) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+		let task_spawner = spawner.clone();
+		let running = spawner.named("Simulate Waves").spawn_with_handle(async move {
+			let queued = task_spawner.named("Draw Quad").spawn_with_handle(async {});
+			let tasks = task_spawner.tasks();
+			let tasks: Vec<_> =
+				tasks.into_iter().map(|task| (task.name, task.state, task.polls)).collect();
+			(tasks, queued)
+		});
+
+		let (tasks, queued) = executor.block_on(running).unwrap();
+		assert_eq!(
+			tasks,
+			[
+				(None, TaskState::Waiting, 1),
+				(Some("Simulate Waves".to_owned()), TaskState::Running, 1),
+				(Some("Draw Quad".to_owned()), TaskState::Queued, 0),
+			]
+		);
+		drop((waiting, queued));
+		assert!(spawner.tasks().is_empty());
+	}
+
+	#[test]
+	fn poll_time_adds_up_across_timed_polls() {
+		let clock = Rc::new(timer::ManualClock::new());
+		timer::set_clock(clock.clone());
+		let (executor, spawner) = new_executor_and_spawner();
+		let task_clock = clock.clone();
+		let _handle = spawner.spawn_with_handle(async move {
+			loop {
+				task_clock.advance(Duration::from_millis(3));
+				yield_now().await;
+			}
+		});
+
+		assert!(executor.poll_once());
+		spawner.time_polls(true);
+		assert!(executor.poll_once());
+		assert!(executor.poll_once());
+		let task = &spawner.tasks()[0];
+		assert_eq!(task.polls, 3);
+		assert_eq!(task.poll_time, Duration::from_millis(6));
+	}
+
+	#[test]
+	fn stalled_tasks_reports_tasks_left_waiting() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<()>();
+		let _stuck = spawner.named("Stuck").spawn_with_handle(async move { receiver.recv().await });
+		let (waker_slot, _polls, _busy) = waker_recording_task(&spawner);
+		executor.run_until_stalled();
+
+		for _ in 0..3 {
+			spawner.advance_frame();
+			waker_slot.borrow().as_ref().unwrap().wake_by_ref();
+			executor.run_until_stalled();
+		}
+
+		assert!(spawner.stalled_tasks(3).is_empty());
+		let stalled = spawner.stalled_tasks(2);
+		assert_eq!(stalled.len(), 1);
+		assert_eq!(stalled[0].name.as_deref(), Some("Stuck"));
+		assert_eq!(stalled[0].frames_since_poll, 3);
+
+		sender.try_send(()).unwrap();
+		executor.run_until_stalled();
+		assert!(spawner.stalled_tasks(0).is_empty());
+	}
 }
@@ -0,0 +1,55 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+
+/// Describes a panic caught while polling a task.
+#[derive(Clone, Debug)]
+pub struct TaskPanic {
+	message: String,
+}
+
+impl TaskPanic {
+	pub(crate) fn from_payload(payload: &(dyn Any + Send)) -> Self {
+		let message = if let Some(message) = payload.downcast_ref::<&str>() {
+			message.to_string()
+		} else if let Some(message) = payload.downcast_ref::<String>() {
+			message.clone()
+		} else {
+			"Box<dyn Any>".to_owned()
+		};
+		Self { message }
+	}
+
+	pub fn message(&self) -> &str { &self.message }
+}
+
+impl fmt::Display for TaskPanic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.message) }
+}
+
+type PanicHook = Box<dyn Fn(&TaskPanic)>;
+
+thread_local! {
+	static PANIC_HOOK: RefCell<Option<PanicHook>> = RefCell::new(None);
+}
+
+/// Registers a function to be called whenever a task on this thread panics.
+/// The panic is reported here in addition to the task's `JoinHandle`, so it is
+/// seen even for tasks spawned without one.
+///
+/// Note that `wasm32-unknown-unknown` aborts on panic by default, in which
+/// case there is nothing for the executor to catch.
+pub fn set_task_panic_hook(hook: impl Fn(&TaskPanic) + 'static) {
+	PANIC_HOOK.with(|slot| *slot.borrow_mut() = Some(Box::new(hook)));
+}
+
+/// Removes the hook set by `set_task_panic_hook`.
+pub fn take_task_panic_hook() { PANIC_HOOK.with(|slot| slot.borrow_mut().take()); }
+
+pub(crate) fn report(panic: &TaskPanic) {
+	PANIC_HOOK.with(|slot| {
+		if let Some(hook) = slot.borrow().as_ref() {
+			hook(panic);
+		}
+	});
+}
@@ -0,0 +1,253 @@
+//! A bounded channel where every receiver sees every value, like events
+//! fanned out to several tasks.
+//!
+//! The channel keeps the last `capacity` values. A receiver that falls
+//! further behind than that skips ahead to the oldest value still kept, and
+//! is told how many it missed.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::Poll;
+
+use super::WakerList;
+
+/// Creates a `Sender` and a first `Receiver`, keeping up to `capacity` values
+/// for receivers which haven't caught up yet.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+	assert!(capacity > 0, "broadcast channel capacity must be non-zero");
+	let shared = Rc::new(RefCell::new(Shared {
+		buffer: VecDeque::with_capacity(capacity),
+		first_position: 0,
+		capacity,
+		senders: 1,
+		receivers: 1,
+		wakers: WakerList::default(),
+	}));
+	(Sender { shared: shared.clone() }, Receiver { shared, next_position: 0 })
+}
+
+struct Shared<T> {
+	buffer: VecDeque<T>,
+	/// The position of `buffer[0]` among every value ever sent
+	first_position: u64,
+	capacity: usize,
+	senders: usize,
+	receivers: usize,
+	wakers: WakerList,
+}
+
+/// Sends values to every `Receiver`. Cloning it adds another sender; the
+/// channel closes once the last one is dropped.
+pub struct Sender<T> {
+	shared: Rc<RefCell<Shared<T>>>,
+}
+
+/// Receives every value sent after it was created. Cloning it creates a
+/// receiver at the same position.
+pub struct Receiver<T> {
+	shared: Rc<RefCell<Shared<T>>>,
+	next_position: u64,
+}
+
+/// Every `Receiver` has been dropped. Holds the value that couldn't be sent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "no receivers are left on the channel")
+	}
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvError {
+	/// Every `Sender` has been dropped, and every value has been received.
+	Closed,
+	/// The receiver fell behind, and this many values were dropped before it
+	/// got to them. Receiving again continues from the oldest value kept.
+	Lagged(u64),
+}
+
+impl fmt::Display for RecvError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RecvError::Closed => write!(f, "channel closed"),
+			RecvError::Lagged(missed) => write!(f, "receiver lagged behind by {missed} values"),
+		}
+	}
+}
+
+impl std::error::Error for RecvError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+	/// There's nothing new to receive yet.
+	Empty,
+	/// See `RecvError::Closed`.
+	Closed,
+	/// See `RecvError::Lagged`.
+	Lagged(u64),
+}
+
+impl<T> Sender<T> {
+	/// Sends `value` to every receiver, returning how many there are. Fails,
+	/// handing `value` back, if there are none.
+	pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+		let wakers = {
+			let mut shared = self.shared.borrow_mut();
+			if shared.receivers == 0 {
+				return Err(SendError(value));
+			}
+			if shared.buffer.len() == shared.capacity {
+				shared.buffer.pop_front();
+				shared.first_position += 1;
+			}
+			shared.buffer.push_back(value);
+			shared.wakers.take()
+		};
+		super::wake_all(wakers);
+		Ok(self.receiver_count())
+	}
+
+	/// Creates a receiver which only sees values sent from now on.
+	pub fn subscribe(&self) -> Receiver<T> {
+		let mut shared = self.shared.borrow_mut();
+		shared.receivers += 1;
+		let next_position = shared.first_position + shared.buffer.len() as u64;
+		Receiver { shared: self.shared.clone(), next_position }
+	}
+
+	pub fn receiver_count(&self) -> usize { self.shared.borrow().receivers }
+}
+
+impl<T> Clone for Sender<T> {
+	fn clone(&self) -> Self {
+		self.shared.borrow_mut().senders += 1;
+		Self { shared: self.shared.clone() }
+	}
+}
+
+impl<T> Drop for Sender<T> {
+	fn drop(&mut self) {
+		let wakers = {
+			let mut shared = self.shared.borrow_mut();
+			shared.senders -= 1;
+			if shared.senders > 0 {
+				return;
+			}
+			shared.wakers.take()
+		};
+		super::wake_all(wakers);
+	}
+}
+
+impl<T: Clone> Receiver<T> {
+	/// Waits for the next value.
+	pub async fn recv(&mut self) -> Result<T, RecvError> {
+		poll_fn(|cx| match self.try_recv() {
+			Ok(value) => Poll::Ready(Ok(value)),
+			Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+			Err(TryRecvError::Lagged(missed)) => Poll::Ready(Err(RecvError::Lagged(missed))),
+			Err(TryRecvError::Empty) => {
+				self.shared.borrow_mut().wakers.register(cx.waker());
+				Poll::Pending
+			}
+		})
+		.await
+	}
+
+	/// Takes the next value if there is one, without waiting.
+	pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+		let shared = self.shared.borrow();
+		if self.next_position < shared.first_position {
+			let missed = shared.first_position - self.next_position;
+			self.next_position = shared.first_position;
+			return Err(TryRecvError::Lagged(missed));
+		}
+
+		let index = (self.next_position - shared.first_position) as usize;
+		match shared.buffer.get(index) {
+			Some(value) => {
+				self.next_position += 1;
+				Ok(value.clone())
+			}
+			None if shared.senders == 0 => Err(TryRecvError::Closed),
+			None => Err(TryRecvError::Empty),
+		}
+	}
+}
+
+impl<T> Clone for Receiver<T> {
+	fn clone(&self) -> Self {
+		self.shared.borrow_mut().receivers += 1;
+		Self { shared: self.shared.clone(), next_position: self.next_position }
+	}
+}
+
+impl<T> Drop for Receiver<T> {
+	fn drop(&mut self) { self.shared.borrow_mut().receivers -= 1; }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn every_receiver_sees_every_value() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = channel(4);
+
+		let handles: Vec<_> = (0..2)
+			.map(|_| {
+				let mut receiver = receiver.clone();
+				spawner.spawn_with_handle(async move {
+					let mut received = Vec::new();
+					while let Ok(value) = receiver.recv().await {
+						received.push(value);
+					}
+					received
+				})
+			})
+			.collect();
+		drop(receiver);
+		executor.run_until_stalled();
+
+		assert_eq!(sender.send("resize"), Ok(2));
+		assert_eq!(sender.send("click"), Ok(2));
+		drop(sender);
+
+		for received in executor.block_on(futures::future::join_all(handles)) {
+			assert_eq!(received.unwrap(), ["resize", "click"]);
+		}
+	}
+
+	#[test]
+	fn slow_receiver_lags() {
+		let (sender, mut receiver) = channel(2);
+		for frame in 0..5 {
+			sender.send(frame).unwrap();
+		}
+
+		assert_eq!(receiver.try_recv(), Err(TryRecvError::Lagged(3)));
+		assert_eq!(receiver.try_recv(), Ok(3));
+		assert_eq!(receiver.try_recv(), Ok(4));
+		assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+	}
+
+	#[test]
+	fn subscriber_starts_at_the_end() {
+		let (sender, receiver) = channel(2);
+		sender.send(1).unwrap();
+		let mut subscriber = sender.subscribe();
+		assert_eq!(subscriber.try_recv(), Err(TryRecvError::Empty));
+
+		drop((sender, receiver));
+		assert_eq!(subscriber.try_recv(), Err(TryRecvError::Closed));
+	}
+}
@@ -0,0 +1,38 @@
+//! Synchronization primitives for tasks sharing the executor's thread.
+//!
+//! These are the `!Send` counterparts of the usual async channels and locks.
+//! With only one thread to worry about, their state lives in plain `Rc`s and
+//! `RefCell`s, with no atomics involved. Waiting is always fair: locks and
+//! permits are handed out in the order they were asked for.
+
+use std::task::Waker;
+
+pub mod broadcast;
+mod mutex;
+pub mod oneshot;
+mod rwlock;
+mod semaphore;
+pub mod watch;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+/// The wakers of every task waiting on something, with each task registered
+/// at most once no matter how often it's polled.
+#[derive(Default)]
+struct WakerList(Vec<Waker>);
+
+impl WakerList {
+	fn register(&mut self, waker: &Waker) {
+		if !self.0.iter().any(|registered| registered.will_wake(waker)) {
+			self.0.push(waker.clone());
+		}
+	}
+
+	/// Takes the wakers out, so they can be woken once any borrows of the state
+	/// holding this list have been released.
+	fn take(&mut self) -> Vec<Waker> { std::mem::take(&mut self.0) }
+}
+
+fn wake_all(wakers: Vec<Waker>) { wakers.into_iter().for_each(Waker::wake); }
@@ -0,0 +1,103 @@
+use std::cell::{RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
+
+use super::{Semaphore, SemaphorePermit};
+
+/// An async mutex, for state that has to stay locked across `.await`s.
+///
+/// If the lock is never held over an `.await`, a plain `RefCell` does the
+/// same job for less.
+pub struct Mutex<T: ?Sized> {
+	semaphore: Semaphore,
+	value: RefCell<T>,
+}
+
+/// Exclusive access to the value in a `Mutex`, until dropped.
+#[must_use = "dropping a MutexGuard unlocks the mutex immediately"]
+pub struct MutexGuard<'a, T: ?Sized> {
+	// Declared first, so the borrow ends before the lock is released
+	value: RefMut<'a, T>,
+	_permit: SemaphorePermit<'a>,
+}
+
+impl<T> Mutex<T> {
+	pub fn new(value: T) -> Self {
+		Self { semaphore: Semaphore::new(1), value: RefCell::new(value) }
+	}
+
+	pub fn into_inner(self) -> T { self.value.into_inner() }
+}
+
+impl<T: ?Sized> Mutex<T> {
+	/// Waits for the lock. Tasks get it in the order they asked for it.
+	pub async fn lock(&self) -> MutexGuard<'_, T> {
+		let permit = self.semaphore.acquire().await;
+		MutexGuard { value: self.value.borrow_mut(), _permit: permit }
+	}
+
+	pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+		let permit = self.semaphore.try_acquire()?;
+		Some(MutexGuard { value: self.value.borrow_mut(), _permit: permit })
+	}
+
+	pub fn get_mut(&mut self) -> &mut T { self.value.get_mut() }
+}
+
+impl<T: Default> Default for Mutex<T> {
+	fn default() -> Self { Self::new(T::default()) }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T { &self.value }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T { &mut self.value }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn lock_is_held_across_await() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let mutex = Rc::new(Mutex::new(Vec::new()));
+		let (resume, resumed) = async_channel::unbounded::<()>();
+
+		let first_mutex = mutex.clone();
+		let first = spawner.spawn_with_handle(async move {
+			let mut log = first_mutex.lock().await;
+			log.push("first locked");
+			resumed.recv().await.unwrap();
+			log.push("first unlocked");
+		});
+		let second_mutex = mutex.clone();
+		let second = spawner.spawn_with_handle(async move {
+			second_mutex.lock().await.push("second locked");
+		});
+
+		executor.run_until_stalled();
+		assert!(mutex.try_lock().is_none());
+
+		resume.try_send(()).unwrap();
+		executor.block_on(async { (first.await.unwrap(), second.await.unwrap()) });
+		assert_eq!(*mutex.try_lock().unwrap(), ["first locked", "first unlocked", "second locked"]);
+	}
+
+	#[test]
+	fn try_lock_fails_while_locked() {
+		let mutex = Mutex::new(1);
+		let mut guard = mutex.try_lock().unwrap();
+		*guard += 1;
+		assert!(mutex.try_lock().is_none());
+
+		drop(guard);
+		assert_eq!(mutex.into_inner(), 2);
+	}
+}
@@ -0,0 +1,164 @@
+//! A channel for sending a single value from one task to another.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Creates a connected `Sender` and `Receiver`.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+	let shared = Rc::new(RefCell::new(Shared {
+		value: None,
+		sender_alive: true,
+		receiver_alive: true,
+		waker: None,
+	}));
+	(Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+struct Shared<T> {
+	value: Option<T>,
+	sender_alive: bool,
+	receiver_alive: bool,
+	waker: Option<Waker>,
+}
+
+/// Sends the channel's one value.
+pub struct Sender<T> {
+	shared: Rc<RefCell<Shared<T>>>,
+}
+
+/// Awaits the channel's one value.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Receiver<T> {
+	shared: Rc<RefCell<Shared<T>>>,
+}
+
+/// The `Sender` was dropped without sending a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "sender dropped without sending a value")
+	}
+}
+
+impl std::error::Error for RecvError {}
+
+/// Why `Receiver::try_recv` returned without a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+	/// No value has been sent yet.
+	Empty,
+	/// The `Sender` was dropped without sending a value, or the value has
+	/// already been received.
+	Closed,
+}
+
+impl<T> Sender<T> {
+	/// Sends `value`, handing it back if the `Receiver` has been dropped.
+	pub fn send(self, value: T) -> Result<(), T> {
+		let mut shared = self.shared.borrow_mut();
+		if !shared.receiver_alive {
+			return Err(value);
+		}
+		// The receiver is woken once `self` is dropped
+		shared.value = Some(value);
+		Ok(())
+	}
+
+	/// Returns true if the `Receiver` has been dropped, so sending is
+	/// pointless.
+	pub fn is_closed(&self) -> bool { !self.shared.borrow().receiver_alive }
+}
+
+impl<T> Drop for Sender<T> {
+	fn drop(&mut self) {
+		let waker = {
+			let mut shared = self.shared.borrow_mut();
+			shared.sender_alive = false;
+			shared.waker.take()
+		};
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+}
+
+impl<T> Receiver<T> {
+	/// Takes the value if it has been sent, without waiting.
+	pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+		let mut shared = self.shared.borrow_mut();
+		match shared.value.take() {
+			Some(value) => Ok(value),
+			None if shared.sender_alive => Err(TryRecvError::Empty),
+			None => Err(TryRecvError::Closed),
+		}
+	}
+}
+
+impl<T> Future for Receiver<T> {
+	type Output = Result<T, RecvError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut shared = self.shared.borrow_mut();
+		match shared.value.take() {
+			Some(value) => Poll::Ready(Ok(value)),
+			None if !shared.sender_alive => Poll::Ready(Err(RecvError)),
+			None => {
+				shared.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+impl<T> Drop for Receiver<T> {
+	fn drop(&mut self) {
+		let mut shared = self.shared.borrow_mut();
+		shared.receiver_alive = false;
+		// A value nobody will receive is dropped now rather than with the sender
+		let value = shared.value.take();
+		drop(shared);
+		drop(value);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn value_reaches_waiting_receiver() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = channel();
+		let received = spawner.spawn_with_handle(receiver);
+		executor.run_until_stalled();
+
+		sender.send("loaded").unwrap();
+		assert_eq!(executor.block_on(received), Ok(Ok("loaded")));
+	}
+
+	#[test]
+	fn dropping_sender_closes_channel() {
+		let (executor, _spawner) = new_executor_and_spawner();
+		let (sender, mut receiver) = channel::<()>();
+		assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+		drop(sender);
+		assert_eq!(receiver.try_recv(), Err(TryRecvError::Closed));
+		assert_eq!(executor.block_on(receiver), Err(RecvError));
+	}
+
+	#[test]
+	fn send_fails_without_receiver() {
+		let (sender, receiver) = channel();
+		drop(receiver);
+		assert!(sender.is_closed());
+		assert_eq!(sender.send(1), Err(1));
+	}
+}
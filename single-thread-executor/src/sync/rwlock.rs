@@ -0,0 +1,125 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
+
+use super::{Semaphore, SemaphorePermit};
+
+/// Readers take one permit each, and writers take all of them.
+const MAX_READERS: usize = u32::MAX as usize;
+
+/// An async reader-writer lock.
+///
+/// Any number of readers can hold the lock at once, or a single writer. A
+/// writer which is waiting holds up readers that arrive after it, so a steady
+/// stream of readers can't starve it.
+pub struct RwLock<T: ?Sized> {
+	semaphore: Semaphore,
+	value: RefCell<T>,
+}
+
+/// Shared access to the value in a `RwLock`, until dropped.
+#[must_use = "dropping a RwLockReadGuard unlocks the lock immediately"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+	value: Ref<'a, T>,
+	_permit: SemaphorePermit<'a>,
+}
+
+/// Exclusive access to the value in a `RwLock`, until dropped.
+#[must_use = "dropping a RwLockWriteGuard unlocks the lock immediately"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+	value: RefMut<'a, T>,
+	_permit: SemaphorePermit<'a>,
+}
+
+impl<T> RwLock<T> {
+	pub fn new(value: T) -> Self {
+		Self { semaphore: Semaphore::new(MAX_READERS), value: RefCell::new(value) }
+	}
+
+	pub fn into_inner(self) -> T { self.value.into_inner() }
+}
+
+impl<T: ?Sized> RwLock<T> {
+	pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+		let permit = self.semaphore.acquire().await;
+		RwLockReadGuard { value: self.value.borrow(), _permit: permit }
+	}
+
+	pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+		let permit = self.semaphore.acquire_many(MAX_READERS).await;
+		RwLockWriteGuard { value: self.value.borrow_mut(), _permit: permit }
+	}
+
+	pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+		let permit = self.semaphore.try_acquire()?;
+		Some(RwLockReadGuard { value: self.value.borrow(), _permit: permit })
+	}
+
+	pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+		let permit = self.semaphore.try_acquire_many(MAX_READERS)?;
+		Some(RwLockWriteGuard { value: self.value.borrow_mut(), _permit: permit })
+	}
+
+	pub fn get_mut(&mut self) -> &mut T { self.value.get_mut() }
+}
+
+impl<T: Default> Default for RwLock<T> {
+	fn default() -> Self { Self::new(T::default()) }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T { &self.value }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T { &self.value }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+	fn deref_mut(&mut self) -> &mut T { &mut self.value }
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn readers_share_the_lock() {
+		let lock = RwLock::new(5);
+		let first = lock.try_read().unwrap();
+		let second = lock.try_read().unwrap();
+		assert_eq!(*first + *second, 10);
+		assert!(lock.try_write().is_none());
+
+		drop((first, second));
+		*lock.try_write().unwrap() += 1;
+		assert_eq!(lock.into_inner(), 6);
+	}
+
+	#[test]
+	fn waiting_writer_holds_up_later_readers() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let lock = Rc::new(RwLock::new(0));
+		let reader = lock.try_read().unwrap();
+
+		let writer_lock = lock.clone();
+		let writer = spawner.spawn_with_handle(async move { *writer_lock.write().await = 1 });
+		executor.run_until_stalled();
+		assert!(lock.try_read().is_none());
+
+		let reader_lock = lock.clone();
+		let later_reader = spawner.spawn_with_handle(async move { *reader_lock.read().await });
+		executor.run_until_stalled();
+		assert!(!later_reader.is_finished());
+
+		drop(reader);
+		executor.block_on(writer).unwrap();
+		assert_eq!(executor.block_on(later_reader), Ok(1));
+	}
+}
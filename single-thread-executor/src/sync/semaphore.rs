@@ -0,0 +1,242 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// An async counting semaphore.
+///
+/// Permits are handed out first come, first served. A task waiting for
+/// several permits holds up the ones queued behind it, even if there would be
+/// enough permits for them, so large requests can't be starved by small ones.
+pub struct Semaphore {
+	state: RefCell<State>,
+}
+
+struct State {
+	permits: usize,
+	waiters: VecDeque<Rc<Waiter>>,
+}
+
+struct Waiter {
+	permits: usize,
+	granted: Cell<bool>,
+	waker: RefCell<Waker>,
+}
+
+/// Permits taken from a `Semaphore`, which are given back when dropped.
+#[must_use = "dropping a SemaphorePermit releases it immediately"]
+pub struct SemaphorePermit<'a> {
+	semaphore: &'a Semaphore,
+	permits: usize,
+}
+
+/// The future returned by `Semaphore::acquire` and `Semaphore::acquire_many`.
+///
+/// Dropping it gives up its place in the queue, or gives back its permits if
+/// they were granted after its last poll.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Acquire<'a> {
+	semaphore: &'a Semaphore,
+	permits: usize,
+	waiter: Option<Rc<Waiter>>,
+}
+
+impl Semaphore {
+	pub fn new(permits: usize) -> Self {
+		Self { state: RefCell::new(State { permits, waiters: VecDeque::new() }) }
+	}
+
+	pub fn available_permits(&self) -> usize { self.state.borrow().permits }
+
+	/// Adds permits, waking any tasks which can now go ahead.
+	pub fn add_permits(&self, permits: usize) {
+		self.state.borrow_mut().permits += permits;
+		self.grant();
+	}
+
+	pub fn acquire(&self) -> Acquire<'_> { self.acquire_many(1) }
+
+	pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+		Acquire { semaphore: self, permits, waiter: None }
+	}
+
+	pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> { self.try_acquire_many(1) }
+
+	/// Takes `permits` permits if they're available right now. This never jumps
+	/// ahead of tasks which are already waiting.
+	pub fn try_acquire_many(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+		let mut state = self.state.borrow_mut();
+		if !state.waiters.is_empty() || state.permits < permits {
+			return None;
+		}
+		state.permits -= permits;
+		Some(SemaphorePermit { semaphore: self, permits })
+	}
+
+	/// Hands permits to waiters at the front of the queue, for as long as there
+	/// are enough for the next one.
+	fn grant(&self) {
+		let mut woken = Vec::new();
+		{
+			let mut state = self.state.borrow_mut();
+			while let Some(waiter) = state.waiters.front() {
+				if waiter.permits > state.permits {
+					break;
+				}
+				state.permits -= waiter.permits;
+				let waiter = state.waiters.pop_front().unwrap();
+				waiter.granted.set(true);
+				woken.push(waiter.waker.borrow().clone());
+			}
+		}
+		super::wake_all(woken);
+	}
+}
+
+impl SemaphorePermit<'_> {
+	/// Keeps the permits from being given back, shrinking the semaphore.
+	pub fn forget(mut self) { self.permits = 0; }
+}
+
+impl Drop for SemaphorePermit<'_> {
+	fn drop(&mut self) {
+		if self.permits > 0 {
+			self.semaphore.add_permits(self.permits);
+		}
+	}
+}
+
+impl<'a> Future for Acquire<'a> {
+	type Output = SemaphorePermit<'a>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let semaphore = self.semaphore;
+		let permits = self.permits;
+		match &self.waiter {
+			Some(waiter) if waiter.granted.get() => {
+				self.waiter = None;
+				Poll::Ready(SemaphorePermit { semaphore, permits })
+			}
+			Some(waiter) => {
+				waiter.waker.borrow_mut().clone_from(cx.waker());
+				Poll::Pending
+			}
+			None => {
+				if let Some(permit) = semaphore.try_acquire_many(permits) {
+					return Poll::Ready(permit);
+				}
+				let waiter = Rc::new(Waiter {
+					permits,
+					granted: Cell::new(false),
+					waker: RefCell::new(cx.waker().clone()),
+				});
+				semaphore.state.borrow_mut().waiters.push_back(waiter.clone());
+				self.waiter = Some(waiter);
+				Poll::Pending
+			}
+		}
+	}
+}
+
+impl Drop for Acquire<'_> {
+	fn drop(&mut self) {
+		let Some(waiter) = self.waiter.take() else {
+			return;
+		};
+		if waiter.granted.get() {
+			self.semaphore.add_permits(waiter.permits);
+		} else {
+			self.semaphore.state.borrow_mut().waiters.retain(|queued| !Rc::ptr_eq(queued, &waiter));
+			// We may have been holding up smaller requests behind us
+			self.semaphore.grant();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn permits_limit_concurrency() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let semaphore = Rc::new(Semaphore::new(2));
+		let (release, released) = async_channel::unbounded::<()>();
+		let running = Rc::new(Cell::new(0));
+
+		let handles: Vec<_> = (0..3)
+			.map(|_| {
+				let semaphore = semaphore.clone();
+				let released = released.clone();
+				let running = running.clone();
+				spawner.spawn_with_handle(async move {
+					let _permit = semaphore.acquire().await;
+					running.set(running.get() + 1);
+					released.recv().await.unwrap();
+					running.set(running.get() - 1);
+				})
+			})
+			.collect();
+
+		executor.run_until_stalled();
+		assert_eq!(running.get(), 2);
+		assert_eq!(semaphore.available_permits(), 0);
+
+		release.try_send(()).unwrap();
+		executor.run_until_stalled();
+		assert_eq!(running.get(), 2);
+
+		release.try_send(()).unwrap();
+		release.try_send(()).unwrap();
+		executor.block_on(futures::future::join_all(handles));
+		assert_eq!(semaphore.available_permits(), 2);
+	}
+
+	#[test]
+	fn large_request_is_not_overtaken() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let semaphore = Rc::new(Semaphore::new(1));
+		let held = semaphore.try_acquire().unwrap();
+
+		let waiting = semaphore.clone();
+		let large =
+			spawner.spawn_with_handle(async move { waiting.acquire_many(2).await.forget() });
+		executor.run_until_stalled();
+
+		semaphore.add_permits(1);
+		// One permit is free, but the queued request for two comes first
+		assert!(semaphore.try_acquire().is_none());
+
+		drop(held);
+		executor.block_on(large).unwrap();
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+
+	#[test]
+	fn dropped_acquire_gives_up_its_place() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let semaphore = Rc::new(Semaphore::new(1));
+		let held = semaphore.try_acquire().unwrap();
+
+		let waiting = semaphore.clone();
+		let large = spawner.spawn_with_handle(async move {
+			let _permits = waiting.acquire_many(2).await;
+		});
+		let waiting = semaphore.clone();
+		let small = spawner.spawn_with_handle(async move {
+			let _permit = waiting.acquire().await;
+		});
+		executor.run_until_stalled();
+
+		large.abort();
+		drop(held);
+		executor.block_on(small).unwrap();
+		assert_eq!(semaphore.available_permits(), 1);
+	}
+}
@@ -0,0 +1,213 @@
+//! A channel which only keeps the latest value, for state that many tasks
+//! want to follow (like the current viewport size) without seeing every
+//! intermediate change.
+
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use std::future::poll_fn;
+use std::rc::Rc;
+use std::task::Poll;
+
+use super::WakerList;
+
+/// Creates a `Sender` and a first `Receiver`, starting out at `initial`.
+///
+/// The initial value counts as already seen, so `Receiver::changed` waits for
+/// the first `send`.
+pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+	let shared = Rc::new(Shared {
+		value: RefCell::new(initial),
+		version: Cell::new(0),
+		sender_alive: Cell::new(true),
+		receivers: Cell::new(1),
+		wakers: RefCell::default(),
+	});
+	(Sender { shared: shared.clone() }, Receiver { shared, seen_version: 0 })
+}
+
+struct Shared<T> {
+	value: RefCell<T>,
+	/// Bumped by every send
+	version: Cell<u64>,
+	sender_alive: Cell<bool>,
+	receivers: Cell<usize>,
+	wakers: RefCell<WakerList>,
+}
+
+/// Publishes new values to every `Receiver`.
+pub struct Sender<T> {
+	shared: Rc<Shared<T>>,
+}
+
+/// Follows the latest value sent on the channel.
+pub struct Receiver<T> {
+	shared: Rc<Shared<T>>,
+	seen_version: u64,
+}
+
+/// Every `Receiver` has been dropped. Holds the value that couldn't be sent.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "no receivers are left on the channel")
+	}
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+/// The `Sender` was dropped, so the value will never change again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "sender dropped") }
+}
+
+impl std::error::Error for RecvError {}
+
+impl<T> Shared<T> {
+	fn notify(&self) { super::wake_all(self.wakers.borrow_mut().take()); }
+}
+
+impl<T> Sender<T> {
+	/// Replaces the value and notifies every receiver. Fails, handing `value`
+	/// back, if there are no receivers left.
+	pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+		if self.shared.receivers.get() == 0 {
+			return Err(SendError(value));
+		}
+		self.send_replace(value);
+		Ok(())
+	}
+
+	/// Replaces the value even if nobody is listening, returning the old one.
+	pub fn send_replace(&self, value: T) -> T {
+		let old = self.shared.value.replace(value);
+		self.shared.version.set(self.shared.version.get() + 1);
+		self.shared.notify();
+		old
+	}
+
+	/// Borrows the latest value. This must not be held across an `.await`, or
+	/// sends from other tasks will panic.
+	pub fn borrow(&self) -> Ref<'_, T> { self.shared.value.borrow() }
+
+	/// Creates a receiver which has already seen the latest value.
+	pub fn subscribe(&self) -> Receiver<T> {
+		self.shared.receivers.set(self.shared.receivers.get() + 1);
+		Receiver { shared: self.shared.clone(), seen_version: self.shared.version.get() }
+	}
+
+	pub fn receiver_count(&self) -> usize { self.shared.receivers.get() }
+
+	/// Returns true if every receiver has been dropped.
+	pub fn is_closed(&self) -> bool { self.receiver_count() == 0 }
+}
+
+impl<T> Drop for Sender<T> {
+	fn drop(&mut self) {
+		self.shared.sender_alive.set(false);
+		self.shared.notify();
+	}
+}
+
+impl<T> Receiver<T> {
+	/// Borrows the latest value, without marking it as seen. This must not be
+	/// held across an `.await`, or sends from other tasks will panic.
+	pub fn borrow(&self) -> Ref<'_, T> { self.shared.value.borrow() }
+
+	/// Borrows the latest value and marks it as seen.
+	pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+		self.seen_version = self.shared.version.get();
+		self.shared.value.borrow()
+	}
+
+	/// Returns true if a value has been sent since this receiver last saw one.
+	pub fn has_changed(&self) -> Result<bool, RecvError> {
+		if !self.shared.sender_alive.get() {
+			return Err(RecvError);
+		}
+		Ok(self.seen_version != self.shared.version.get())
+	}
+
+	/// Waits for a value this receiver hasn't seen yet, and marks it as seen.
+	/// Fails once the sender is gone, unless there's an unseen value left.
+	pub async fn changed(&mut self) -> Result<(), RecvError> {
+		poll_fn(|cx| {
+			let version = self.shared.version.get();
+			if version != self.seen_version {
+				self.seen_version = version;
+				return Poll::Ready(Ok(()));
+			}
+			if !self.shared.sender_alive.get() {
+				return Poll::Ready(Err(RecvError));
+			}
+			self.shared.wakers.borrow_mut().register(cx.waker());
+			Poll::Pending
+		})
+		.await
+	}
+}
+
+impl<T> Clone for Receiver<T> {
+	fn clone(&self) -> Self {
+		self.shared.receivers.set(self.shared.receivers.get() + 1);
+		Self { shared: self.shared.clone(), seen_version: self.seen_version }
+	}
+}
+
+impl<T> Drop for Receiver<T> {
+	fn drop(&mut self) { self.shared.receivers.set(self.shared.receivers.get() - 1); }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn receivers_only_see_the_latest_value() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, mut receiver) = channel((800, 600));
+		let mut other = receiver.clone();
+
+		let seen = spawner.spawn_with_handle(async move {
+			other.changed().await.unwrap();
+			*other.borrow_and_update()
+		});
+		executor.run_until_stalled();
+
+		sender.send((1024, 768)).unwrap();
+		sender.send((1920, 1080)).unwrap();
+		assert_eq!(executor.block_on(seen), Ok((1920, 1080)));
+
+		assert!(receiver.has_changed().unwrap());
+		executor.block_on(receiver.changed()).unwrap();
+		assert!(!receiver.has_changed().unwrap());
+	}
+
+	#[test]
+	fn dropping_sender_ends_changed() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, mut receiver) = channel(0);
+		let waiting = spawner.spawn_with_handle(async move { receiver.changed().await });
+		executor.run_until_stalled();
+
+		drop(sender);
+		assert_eq!(executor.block_on(waiting), Ok(Err(RecvError)));
+	}
+
+	#[test]
+	fn send_fails_without_receivers() {
+		let (sender, receiver) = channel(0);
+		let subscriber = sender.subscribe();
+		assert_eq!(sender.receiver_count(), 2);
+
+		drop((receiver, subscriber));
+		assert_eq!(sender.send(1), Err(SendError(1)));
+		assert_eq!(sender.send_replace(2), 0);
+		assert_eq!(*sender.borrow(), 2);
+	}
+}
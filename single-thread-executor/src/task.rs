@@ -0,0 +1,263 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use crate::join_handle::Failure;
+use crate::{timer, JoinError, Shared, TaskInfo, TaskPanic, TaskState};
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+/// A spawned future, plus what it needs to reschedule itself when woken.
+pub(crate) struct Task {
+	id: u64,
+	name: Option<String>,
+	/// Empty while the future is being polled, and for good once it finishes
+	future: RefCell<Option<LocalFuture>>,
+	shared: Weak<Shared>,
+	/// Where this is found in `TASK_SLOTS`
+	slot: usize,
+	waker: Waker,
+	failure: Failure,
+	/// Set while the task sits in the ready queue, so that waking it several
+	/// times before it's polled only queues it once
+	scheduled: Cell<bool>,
+	/// Set once the future has completed or been dropped
+	retired: Cell<bool>,
+	polls: Cell<u64>,
+	poll_time: Cell<Duration>,
+	/// The executor's frame count as of the last poll
+	last_polled_frame: Cell<u64>,
+}
+
+impl Task {
+	pub(crate) fn new(
+		future: impl Future<Output = ()> + 'static,
+		shared: &Rc<Shared>,
+		failure: Failure,
+		name: Option<String>,
+	) -> Rc<Self> {
+		let id = shared.next_task_id.get();
+		shared.next_task_id.set(id + 1);
+
+		let task = Rc::new_cyclic(|task| {
+			let waker = TASK_SLOTS.with(|slots| slots.borrow_mut().insert(task.clone()));
+			Self {
+				id,
+				name,
+				future: RefCell::new(Some(Box::pin(future))),
+				shared: Rc::downgrade(shared),
+				slot: waker.slot,
+				waker: Arc::new(waker).into(),
+				failure,
+				scheduled: Cell::new(false),
+				retired: Cell::new(false),
+				polls: Cell::new(0),
+				poll_time: Cell::new(Duration::ZERO),
+				last_polled_frame: Cell::new(shared.frame.get()),
+			}
+		});
+		shared.tasks.borrow_mut().insert(id, task.clone());
+		task
+	}
+
+	/// Polls the task's future, returning the time the poll finished if it
+	/// was timed.
+	pub(crate) fn poll(self: &Rc<Self>) -> Option<Duration> {
+		// Cleared first, so wakeups from within this poll queue the task again
+		self.scheduled.set(false);
+
+		// Taken out of the slot so that the future is free to cancel itself, or
+		// wake itself, without running into a borrow of its own slot.
+		let mut future = self.future.borrow_mut().take()?;
+
+		let context = &mut Context::from_waker(&self.waker);
+
+		self.polls.set(self.polls.get() + 1);
+		let started = self.shared.upgrade().and_then(|shared| {
+			self.last_polled_frame.set(shared.frame.get());
+			shared.time_polls.get().then(timer::now)
+		});
+		let result = panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(context)));
+		let finished = started.map(|started| {
+			let finished = timer::now();
+			self.poll_time.set(self.poll_time.get() + finished.saturating_sub(started));
+			finished
+		});
+
+		// A panic only takes down the task that raised it. The failure is recorded
+		// before the future is dropped, so its `JoinHandle` can report it.
+		match result {
+			Ok(Poll::Pending) if !self.is_cancelled() => *self.future.borrow_mut() = Some(future),
+			Ok(_) => {
+				drop(future);
+				self.retire();
+			}
+			Err(payload) => {
+				let panic = TaskPanic::from_payload(&*payload);
+				*self.failure.borrow_mut() = Some(JoinError::Panicked(panic.clone()));
+				drop(future);
+				self.retire();
+				crate::panic::report(&panic);
+			}
+		}
+		finished
+	}
+
+	/// Drops the task's future so it never runs again. If the task is being
+	/// polled right now (i.e. it aborted itself), the future is dropped once
+	/// that poll returns instead.
+	pub(crate) fn cancel(&self) {
+		if self.retired.get() {
+			return;
+		}
+		*self.failure.borrow_mut() = Some(JoinError::Cancelled);
+		let future = self.future.borrow_mut().take();
+		if future.is_some() {
+			drop(future);
+			self.retire();
+		}
+	}
+
+	pub(crate) fn info(&self, frame: u64) -> TaskInfo {
+		let state = if self.scheduled.get() {
+			TaskState::Queued
+		} else if self.future.borrow().is_none() {
+			// Live tasks only lose their future while it's being polled
+			TaskState::Running
+		} else {
+			TaskState::Waiting
+		};
+		TaskInfo {
+			id: self.id,
+			name: self.name.clone(),
+			state,
+			polls: self.polls.get(),
+			poll_time: self.poll_time.get(),
+			frames_since_poll: frame - self.last_polled_frame.get(),
+		}
+	}
+
+	fn is_cancelled(&self) -> bool { matches!(*self.failure.borrow(), Some(JoinError::Cancelled)) }
+
+	pub(crate) fn schedule(self: Rc<Self>) {
+		if self.scheduled.get() || self.retired.get() {
+			return;
+		}
+		if let Some(shared) = self.shared.upgrade() {
+			self.scheduled.set(true);
+			shared.schedule(self);
+		}
+	}
+
+	/// Marks the task's future as gone for good.
+	fn retire(&self) {
+		self.retired.set(true);
+		if let Some(shared) = self.shared.upgrade() {
+			// Dropped outside the borrow, in case it's the task's last reference
+			let removed = shared.tasks.borrow_mut().remove(&self.id);
+			drop(removed);
+			if shared.tasks.borrow().is_empty() {
+				if let Some(waker) = shared.run_waker.borrow_mut().take() {
+					waker.wake();
+				}
+			}
+		}
+	}
+}
+
+impl Drop for Task {
+	fn drop(&mut self) {
+		if self.future.get_mut().take().is_some() {
+			self.retire();
+		}
+		// Fails only while the thread is shutting down, when nothing can be woken
+		let _ = TASK_SLOTS.try_with(|slots| slots.borrow_mut().remove(self.slot));
+	}
+}
+
+thread_local! {
+	/// Every task on this thread, from any of its executors
+	static TASK_SLOTS: RefCell<TaskSlots> = RefCell::new(TaskSlots::new());
+}
+
+/// Lets wakers find their task by index, rather than holding on to it. A slot
+/// is reused once its task is dropped, with a new generation, so that wakers
+/// left over from the old task don't find the new one.
+struct TaskSlots {
+	thread: ThreadId,
+	slots: Vec<TaskSlot>,
+	free: Vec<usize>,
+}
+
+struct TaskSlot {
+	generation: u64,
+	task: Weak<Task>,
+}
+
+impl TaskSlots {
+	fn new() -> Self {
+		Self { thread: thread::current().id(), slots: Vec::new(), free: Vec::new() }
+	}
+
+	fn insert(&mut self, task: Weak<Task>) -> TaskWaker {
+		let slot = match self.free.pop() {
+			Some(slot) => {
+				self.slots[slot].task = task;
+				slot
+			}
+			None => {
+				self.slots.push(TaskSlot { generation: 0, task });
+				self.slots.len() - 1
+			}
+		};
+		TaskWaker { slot, generation: self.slots[slot].generation, thread: self.thread }
+	}
+
+	fn remove(&mut self, slot: usize) {
+		let removed = &mut self.slots[slot];
+		removed.generation += 1;
+		removed.task = Weak::new();
+		self.free.push(slot);
+	}
+
+	fn find(&self, waker: &TaskWaker) -> Option<Rc<Task>> {
+		// Each thread has its own slots, so another thread's would give the wrong
+		// task, or none
+		assert_eq!(
+			waker.thread, self.thread,
+			"tasks can only be woken from their executor's thread"
+		);
+		let slot = &self.slots[waker.slot];
+		if slot.generation != waker.generation {
+			return None;
+		}
+		slot.task.upgrade()
+	}
+}
+
+/// Wakes a task by looking up its slot on the task's own thread. A `Waker` can
+/// be sent to any thread, but the task and its executor can't be, so this holds
+/// nothing of theirs, and wakeups from any other thread are refused.
+struct TaskWaker {
+	slot: usize,
+	generation: u64,
+	thread: ThreadId,
+}
+
+impl Wake for TaskWaker {
+	fn wake(self: Arc<Self>) { self.wake_by_ref(); }
+
+	fn wake_by_ref(self: &Arc<Self>) {
+		// Finished tasks, and those whose executor is gone, are simply not found
+		let task = TASK_SLOTS.try_with(|slots| slots.borrow().find(self)).ok().flatten();
+		if let Some(task) = task {
+			task.schedule();
+		}
+	}
+}
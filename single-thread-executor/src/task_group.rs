@@ -0,0 +1,182 @@
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::Poll;
+
+use crate::{JoinError, JoinHandle, SpawnError, Spawner};
+
+/// A set of related tasks whose lifetimes are tied together.
+///
+/// `join` waits for every child to finish. As soon as it sees one fail, it
+/// cancels the rest and reports that first failure. Dropping the group
+/// without joining it cancels every child that is still running.
+pub struct TaskGroup<E> {
+	spawner: Spawner,
+	children: Vec<JoinHandle<Result<(), E>>>,
+}
+
+/// The first failure seen by `TaskGroup::join`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GroupError<E> {
+	/// A child returned an error.
+	Failed(E),
+	/// A child panicked, or was cancelled or dropped from outside the group.
+	Join(JoinError),
+}
+
+impl<E: fmt::Display> fmt::Display for GroupError<E> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			GroupError::Failed(error) => write!(f, "task failed: {error}"),
+			GroupError::Join(error) => write!(f, "{error}"),
+		}
+	}
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for GroupError<E> {}
+
+impl<E: 'static> TaskGroup<E> {
+	pub fn new(spawner: &Spawner) -> Self {
+		Self { spawner: spawner.clone(), children: Vec::new() }
+	}
+
+	/// Spawns a child task into the group.
+	///
+	/// Panics if the task can't be queued; use `try_spawn` to handle that case.
+	pub fn spawn(&mut self, future: impl Future<Output = Result<(), E>> + 'static) {
+		self.try_spawn(future).expect("failed to spawn task");
+	}
+
+	pub fn try_spawn(
+		&mut self,
+		future: impl Future<Output = Result<(), E>> + 'static,
+	) -> Result<(), SpawnError> {
+		let handle = self.spawner.try_spawn_with_handle(future)?;
+		self.children.push(handle);
+		Ok(())
+	}
+
+	/// The number of children which haven't been collected by `join` yet.
+	pub fn len(&self) -> usize { self.children.len() }
+
+	pub fn is_empty(&self) -> bool { self.children.is_empty() }
+
+	/// Cancels every child that is still running.
+	pub fn abort_all(&self) { self.children.iter().for_each(JoinHandle::abort); }
+
+	/// Waits for every child to finish, or for the first one to fail, in which
+	/// case the rest are cancelled and the failure is returned.
+	pub async fn join(mut self) -> Result<(), GroupError<E>> {
+		let mut first_error = None;
+
+		poll_fn(|cx| loop {
+			let had_error = first_error.is_some();
+			self.children.retain_mut(|child| match Pin::new(child).poll(cx) {
+				Poll::Pending => true,
+				Poll::Ready(result) => {
+					let error = match result {
+						Ok(Ok(())) => None,
+						Ok(Err(error)) => Some(GroupError::Failed(error)),
+						// Expected, once we've started cancelling the rest
+						Err(JoinError::Cancelled) if had_error => None,
+						Err(error) => Some(GroupError::Join(error)),
+					};
+					if first_error.is_none() {
+						first_error = error;
+					}
+					false
+				}
+			});
+
+			if self.children.is_empty() {
+				return Poll::Ready(());
+			}
+			if had_error || first_error.is_none() {
+				return Poll::Pending;
+			}
+			// Cancelling finishes the remaining children immediately, so go
+			// around again to collect them.
+			self.abort_all();
+		})
+		.await;
+
+		first_error.map_or(Ok(()), Err)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	#[test]
+	fn join_waits_for_every_child() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let finished = Rc::new(Cell::new(0));
+		let mut group = TaskGroup::<()>::new(&spawner);
+		for _ in 0..3 {
+			let finished = finished.clone();
+			group.spawn(async move {
+				finished.set(finished.get() + 1);
+				Ok(())
+			});
+		}
+
+		assert_eq!(executor.block_on(group.join()), Ok(()));
+		assert_eq!(finished.get(), 3);
+	}
+
+	#[test]
+	fn first_failure_cancels_the_rest() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (sender, receiver) = async_channel::unbounded::<()>();
+		let mut group = TaskGroup::new(&spawner);
+		group.spawn(async move {
+			receiver.recv().await.unwrap();
+			Ok(())
+		});
+		group.spawn(async { Err("asset missing") });
+
+		assert_eq!(executor.block_on(group.join()), Err(GroupError::Failed("asset missing")));
+		// The waiting child was dropped, taking its receiver with it
+		assert!(sender.is_closed());
+	}
+
+	#[test]
+	fn panicking_child_fails_the_group() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let (_sender, receiver) = async_channel::unbounded::<()>();
+		let mut group = TaskGroup::<()>::new(&spawner);
+		group.spawn(async move {
+			receiver.recv().await.unwrap();
+			Ok(())
+		});
+		group.spawn(async { panic!("shader failed to compile") });
+
+		match executor.block_on(group.join()) {
+			Err(GroupError::Join(JoinError::Panicked(panic))) => {
+				assert_eq!(panic.message(), "shader failed to compile")
+			}
+			other => panic!("expected a panic, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn dropping_group_cancels_children() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let polled = Rc::new(Cell::new(false));
+		let mut group = TaskGroup::<()>::new(&spawner);
+		let task_polled = polled.clone();
+		group.spawn(async move {
+			task_polled.set(true);
+			Ok(())
+		});
+
+		drop(group);
+		executor.run_until_stalled();
+		assert!(!polled.get());
+	}
+}
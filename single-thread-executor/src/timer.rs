@@ -0,0 +1,452 @@
+//! Timer futures for tasks running on the executor.
+//!
+//! In the browser these are backed by `setTimeout`. On native targets there's
+//! no event loop to lean on, so timers are kept in a thread-local queue which
+//! the synchronous `Executor` entry points (`poll_once`, `run_until_stalled`
+//! and `block_on`) fire as their deadlines pass. Native time comes from a
+//! pluggable `Clock`, so tests can swap in a `ManualClock` and advance virtual
+//! time deterministically.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use native::{fire_expired, wait_for_next};
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{set_clock, Clock, ManualClock, SystemClock};
+
+/// Returns the current time, measured from an arbitrary fixed starting point.
+pub fn now() -> Duration { platform::now() }
+
+/// Waits until `duration` has passed.
+pub fn sleep(duration: Duration) -> Sleep { sleep_until(now() + duration) }
+
+/// Waits until `now()` reaches `deadline`.
+pub fn sleep_until(deadline: Duration) -> Sleep { Sleep { deadline, registration: None } }
+
+/// Creates an `Interval` which first ticks immediately, then once per `period`.
+pub fn interval(period: Duration) -> Interval {
+	assert!(!period.is_zero(), "interval period must be non-zero");
+	Interval { period, sleep: sleep_until(now()) }
+}
+
+/// Runs `future`, giving up with `Elapsed` if it hasn't finished within
+/// `duration`.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+	Timeout { future: Box::pin(future), sleep: sleep(duration) }
+}
+
+/// Waits for the event loop's next macrotask, giving the browser a chance to
+/// render and handle events first. Native targets have no event loop to yield
+/// to, so there this only lets other ready tasks run.
+pub(crate) async fn yield_to_event_loop() {
+	#[cfg(target_arch = "wasm32")]
+	{
+		// A zero timeout, which unlike `sleep(Duration::ZERO)` always waits for
+		// the callback to fire
+		let mut registration: Option<platform::Registration> = None;
+		std::future::poll_fn(|cx| match registration.as_mut() {
+			Some(registration) if registration.has_fired() => Poll::Ready(()),
+			Some(registration) => {
+				registration.update_waker(cx.waker());
+				Poll::Pending
+			}
+			None => {
+				registration = Some(platform::Registration::new(Duration::ZERO, cx.waker()));
+				Poll::Pending
+			}
+		})
+		.await
+	}
+	#[cfg(not(target_arch = "wasm32"))]
+	crate::yield_now().await
+}
+
+/// A future which completes once its deadline has passed.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Sleep {
+	deadline: Duration,
+	registration: Option<platform::Registration>,
+}
+
+impl Sleep {
+	pub fn deadline(&self) -> Duration { self.deadline }
+
+	/// Moves the deadline, so the sleep can be reused instead of recreated.
+	pub fn reset(&mut self, deadline: Duration) {
+		self.deadline = deadline;
+		self.registration = None;
+	}
+
+	pub fn is_elapsed(&self) -> bool {
+		self.registration.as_ref().is_some_and(|r| r.has_fired()) || now() >= self.deadline
+	}
+}
+
+impl Future for Sleep {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		if self.is_elapsed() {
+			self.registration = None;
+			return Poll::Ready(());
+		}
+
+		let deadline = self.deadline;
+		match self.registration.as_mut() {
+			Some(registration) => registration.update_waker(cx.waker()),
+			None => self.registration = Some(platform::Registration::new(deadline, cx.waker())),
+		}
+		Poll::Pending
+	}
+}
+
+/// Ticks at a fixed period. If a tick is late by more than a whole period, the
+/// missed ticks are skipped rather than fired back-to-back.
+pub struct Interval {
+	period: Duration,
+	sleep: Sleep,
+}
+
+impl Interval {
+	pub fn period(&self) -> Duration { self.period }
+
+	/// Waits for the next tick, returning the time it was scheduled for.
+	pub async fn tick(&mut self) -> Duration { std::future::poll_fn(|cx| self.poll_tick(cx)).await }
+
+	pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Duration> {
+		if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+			return Poll::Pending;
+		}
+
+		let scheduled = self.sleep.deadline();
+		let mut next = scheduled + self.period;
+		let current = now();
+		if next <= current {
+			next = current + self.period;
+		}
+		self.sleep.reset(next);
+		Poll::Ready(scheduled)
+	}
+}
+
+/// The error returned by `Timeout` when its future took too long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "deadline has elapsed") }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// A future which resolves to its inner future's output, or to `Elapsed` if
+/// that takes too long. The inner future is dropped along with the `Timeout`.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Timeout<F: Future> {
+	future: Pin<Box<F>>,
+	sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+	type Output = Result<F::Output, Elapsed>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+			return Poll::Ready(Ok(output));
+		}
+		match Pin::new(&mut self.sleep).poll(cx) {
+			Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+			Poll::Pending => Poll::Pending,
+		}
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+use native as platform;
+#[cfg(target_arch = "wasm32")]
+use web as platform;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+	use std::cell::{Cell, RefCell};
+	use std::collections::BTreeMap;
+	use std::rc::Rc;
+	use std::task::Waker;
+	use std::time::{Duration, Instant};
+
+	/// A source of time for native timers.
+	pub trait Clock {
+		/// The current time, measured from an arbitrary fixed starting point.
+		fn now(&self) -> Duration;
+
+		/// Called by `Executor::block_on` when it has nothing to do until
+		/// `deadline`. Real clocks should block the thread (returning early if
+		/// it's unparked), while virtual clocks can simply jump ahead.
+		fn wait_until(&self, deadline: Duration);
+	}
+
+	/// The default native clock, which follows real time.
+	pub struct SystemClock {
+		origin: Instant,
+	}
+
+	impl SystemClock {
+		pub fn new() -> Self { Self { origin: Instant::now() } }
+	}
+
+	impl Default for SystemClock {
+		fn default() -> Self { Self::new() }
+	}
+
+	impl Clock for SystemClock {
+		fn now(&self) -> Duration { self.origin.elapsed() }
+
+		fn wait_until(&self, deadline: Duration) {
+			std::thread::park_timeout(deadline.saturating_sub(self.now()));
+		}
+	}
+
+	/// A virtual clock which only moves when told to.
+	#[derive(Default)]
+	pub struct ManualClock {
+		now: Cell<Duration>,
+	}
+
+	impl ManualClock {
+		pub fn new() -> Self { Self::default() }
+
+		pub fn advance(&self, by: Duration) { self.now.set(self.now.get() + by); }
+
+		/// Moves the clock to `time`, unless it has already passed it.
+		pub fn advance_to(&self, time: Duration) { self.now.set(self.now.get().max(time)); }
+	}
+
+	impl Clock for ManualClock {
+		fn now(&self) -> Duration { self.now.get() }
+
+		fn wait_until(&self, deadline: Duration) { self.advance_to(deadline); }
+	}
+
+	/// Replaces the clock used by timers on this thread. Timers created before
+	/// the switch keep their deadlines, which are then read against the new
+	/// clock, so this is best done before any timers exist.
+	pub fn set_clock(clock: Rc<dyn Clock>) {
+		TIMERS.with(|timers| timers.borrow_mut().clock = clock);
+	}
+
+	struct TimerQueue {
+		clock: Rc<dyn Clock>,
+		wakers: BTreeMap<(Duration, u64), Waker>,
+		next_id: u64,
+	}
+
+	thread_local! {
+		static TIMERS: RefCell<TimerQueue> = RefCell::new(TimerQueue {
+			clock: Rc::new(SystemClock::new()),
+			wakers: BTreeMap::new(),
+			next_id: 0,
+		});
+	}
+
+	pub fn now() -> Duration { TIMERS.with(|timers| timers.borrow().clock.now()) }
+
+	fn clock() -> Rc<dyn Clock> { TIMERS.with(|timers| timers.borrow().clock.clone()) }
+
+	/// Wakes every timer whose deadline has passed.
+	pub(crate) fn fire_expired() {
+		let expired: Vec<Waker> = TIMERS.with(|timers| {
+			let mut timers = timers.borrow_mut();
+			// This runs before every poll, so the clock is only read if it's needed
+			if timers.wakers.is_empty() {
+				return Vec::new();
+			}
+			let now = timers.clock.now();
+			let still_pending = timers.wakers.split_off(&(now, u64::MAX));
+			std::mem::replace(&mut timers.wakers, still_pending).into_values().collect()
+		});
+		expired.into_iter().for_each(Waker::wake);
+	}
+
+	/// Blocks until the earliest timer is due. Returns false if there are no
+	/// timers to wait for.
+	pub(crate) fn wait_for_next() -> bool {
+		let next = TIMERS.with(|timers| timers.borrow().wakers.keys().next().map(|key| key.0));
+		match next {
+			Some(deadline) => {
+				// Not borrowed while waiting, in case the clock reaches back in here
+				clock().wait_until(deadline);
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub(crate) struct Registration {
+		key: (Duration, u64),
+	}
+
+	impl Registration {
+		pub fn new(deadline: Duration, waker: &Waker) -> Self {
+			TIMERS.with(|timers| {
+				let mut timers = timers.borrow_mut();
+				let key = (deadline, timers.next_id);
+				timers.next_id += 1;
+				timers.wakers.insert(key, waker.clone());
+				Self { key }
+			})
+		}
+
+		pub fn update_waker(&mut self, waker: &Waker) {
+			// Re-inserted if it already fired, e.g. after the clock was swapped
+			TIMERS.with(|timers| timers.borrow_mut().wakers.insert(self.key, waker.clone()));
+		}
+
+		// Firing is tracked by the queue rather than here, and `Sleep` checks the
+		// clock itself anyway.
+		pub fn has_fired(&self) -> bool { false }
+	}
+
+	impl Drop for Registration {
+		fn drop(&mut self) {
+			// Ignore failures, since this can run during thread-local teardown
+			let _ = TIMERS.try_with(|timers| timers.borrow_mut().wakers.remove(&self.key));
+		}
+	}
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+	use std::cell::{Cell, RefCell};
+	use std::rc::Rc;
+	use std::task::Waker;
+	use std::time::Duration;
+
+	use wasm_bindgen::prelude::*;
+
+	#[wasm_bindgen]
+	extern "C" {
+		#[wasm_bindgen(js_name = setTimeout)]
+		fn set_timeout(handler: &Closure<dyn FnMut()>, timeout: i32) -> i32;
+
+		#[wasm_bindgen(js_name = clearTimeout)]
+		fn clear_timeout(handle: i32);
+
+		// Unlike `Date.now`, this has sub-millisecond resolution, which poll
+		// timings need, and never goes backwards.
+		#[wasm_bindgen(js_namespace = performance, js_name = now)]
+		fn performance_now() -> f64;
+	}
+
+	pub fn now() -> Duration { Duration::from_secs_f64(performance_now() / 1000.0) }
+
+	pub(crate) struct Registration {
+		handle: i32,
+		fired: Rc<Cell<bool>>,
+		waker: Rc<RefCell<Waker>>,
+		_callback: Closure<dyn FnMut()>,
+	}
+
+	impl Registration {
+		pub fn new(deadline: Duration, waker: &Waker) -> Self {
+			let fired = Rc::new(Cell::new(false));
+			let waker = Rc::new(RefCell::new(waker.clone()));
+
+			let callback = {
+				let fired = fired.clone();
+				let waker = waker.clone();
+				Closure::wrap(Box::new(move || {
+					fired.set(true);
+					waker.borrow().wake_by_ref();
+				}) as Box<dyn FnMut()>)
+			};
+
+			let millis = deadline.saturating_sub(now()).as_secs_f64() * 1000.0;
+			let handle = set_timeout(&callback, millis.ceil() as i32);
+			Self { handle, fired, waker, _callback: callback }
+		}
+
+		pub fn update_waker(&mut self, waker: &Waker) { self.waker.borrow_mut().clone_from(waker); }
+
+		pub fn has_fired(&self) -> bool { self.fired.get() }
+	}
+
+	impl Drop for Registration {
+		fn drop(&mut self) {
+			if !self.fired.get() {
+				clear_timeout(self.handle);
+			}
+		}
+	}
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use std::rc::Rc;
+	use std::time::Duration;
+
+	use super::*;
+	use crate::new_executor_and_spawner;
+
+	fn manual_clock() -> Rc<ManualClock> {
+		let clock = Rc::new(ManualClock::new());
+		set_clock(clock.clone());
+		clock
+	}
+
+	#[test]
+	fn sleep_waits_for_clock() {
+		let clock = manual_clock();
+		let (executor, spawner) = new_executor_and_spawner();
+		let handle = spawner.spawn_with_handle(sleep(Duration::from_millis(10)));
+
+		executor.run_until_stalled();
+		assert!(!handle.is_finished());
+
+		clock.advance(Duration::from_millis(5));
+		executor.run_until_stalled();
+		assert!(!handle.is_finished());
+
+		clock.advance(Duration::from_millis(5));
+		executor.run_until_stalled();
+		assert!(handle.is_finished());
+	}
+
+	#[test]
+	fn block_on_skips_ahead_on_manual_clock() {
+		let clock = manual_clock();
+		let (executor, _spawner) = new_executor_and_spawner();
+
+		executor.block_on(sleep(Duration::from_secs(60)));
+		assert_eq!(clock.now(), Duration::from_secs(60));
+	}
+
+	#[test]
+	fn timeout_gives_up_after_deadline() {
+		let _clock = manual_clock();
+		let (executor, _spawner) = new_executor_and_spawner();
+
+		let slow = timeout(Duration::from_millis(10), futures::future::pending::<()>());
+		assert_eq!(executor.block_on(slow), Err(Elapsed));
+
+		let fast = timeout(Duration::from_millis(10), async { 3 });
+		assert_eq!(executor.block_on(fast), Ok(3));
+	}
+
+	#[test]
+	fn interval_skips_missed_ticks() {
+		let clock = manual_clock();
+		let (executor, _spawner) = new_executor_and_spawner();
+		let mut ticks = interval(Duration::from_millis(10));
+
+		assert_eq!(executor.block_on(ticks.tick()), Duration::ZERO);
+		assert_eq!(executor.block_on(ticks.tick()), Duration::from_millis(10));
+
+		clock.advance_to(Duration::from_millis(55));
+		assert_eq!(executor.block_on(ticks.tick()), Duration::from_millis(20));
+		assert_eq!(executor.block_on(ticks.tick()), Duration::from_millis(65));
+	}
+}
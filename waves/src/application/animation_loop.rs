@@ -1,49 +1,83 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
-use single_thread_executor::new_executor_and_spawner;
-use wasm_bindgen_futures::spawn_local;
+use single_thread_executor::Spawner;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
 use crate::application::shaders::{load_render_texture_shaders, load_simulation_shaders};
 use crate::application::{pipeline, simulate};
 use crate::render_core::animation::{wrap_animation_body, AnimationFn};
 use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::fixed_timestep::{FixedTimestep, FixedTimestepMarker};
+use crate::render_core::frame_capture::{global_capture, FrameCapture};
+use crate::render_core::frame_profiler::set_page_profiler;
 use crate::render_core::frame_sequencer::{FrameGate, FrameMarker, FrameSequencer};
 use crate::utils::prelude::*;
 
+/// The rate the waves are simulated at, independent of the display's refresh
+/// rate
+const SIMULATION_HZ: f64 = 120.0;
+
+/// What can differ between the waves views on a page.
+pub struct ViewSettings {
+	pub wavelength: f32,
+	/// Whether this is the view the page-wide tools look at, i.e. the frame
+	/// profiler and capture. There should only be one.
+	pub is_main_view: bool,
+}
+
+impl Default for ViewSettings {
+	fn default() -> Self { Self { wavelength: 0.1, is_main_view: false } }
+}
+
+/// Sets up a view's tasks on `spawner`, and returns the animation body which
+/// drives them, along with the timestep it runs their frames through.
 pub fn get_animation_loop(
+	spawner: &Spawner,
 	canvas: HtmlCanvasElement,
 	context: WebGl2RenderingContext,
-) -> Result<AnimationFn, JsValue> {
-	let (executor, spawner) = new_executor_and_spawner();
-	spawn_local(async move {
-		executor.run().await;
-	});
-
+	settings: &ViewSettings,
+) -> Result<(AnimationFn, Rc<RefCell<FixedTimestep>>), JsValue> {
 	let frame_sequencer = Rc::new(FrameSequencer::<AnimationParams>::new());
+	let capture = if settings.is_main_view {
+		set_page_profiler(frame_sequencer.profiler());
+		global_capture()
+	} else {
+		FrameCapture::default()
+	};
 	let simulation_shader =
 		load_simulation_shaders(&context).expect("Failed to load simulation shaders");
 	let render_texture_shader =
 		load_render_texture_shaders(&context).expect("Failed to load render shaders");
 
-	let (sender, receiver) = async_channel::unbounded::<u64>();
+	// Drawing has to wait for the simulation to update its uniforms
+	let simulate_gate = FrameGate::new(frame_sequencer.clone(), "Simulate Waves".to_owned());
+	let draw_gate = FrameGate::with_dependencies(
+		frame_sequencer.clone(),
+		"Draw Quad".to_owned(),
+		&[&simulate_gate],
+	);
 
-	spawner.spawn(simulate::waves(
-		FrameGate::new(frame_sequencer.clone(), "Simulate Waves".to_owned()),
-		sender,
+	spawner.named("Simulate Waves").spawn(simulate::waves(
+		simulate_gate,
 		simulation_shader.clone(),
+		settings.wavelength,
 	));
 
-	spawner.spawn(pipeline::draw_indirect(
-		FrameGate::new(frame_sequencer.clone(), "Draw Quad".to_owned()),
-		receiver,
+	spawner.named("Draw Quad").spawn(pipeline::draw_indirect(
+		draw_gate,
 		simulation_shader.clone(),
 		render_texture_shader,
+		capture,
 	));
 
-	let frame_marker = FrameMarker::new(frame_sequencer.clone());
+	let frame_marker = FixedTimestepMarker::new(
+		FrameMarker::new(frame_sequencer.clone()),
+		FixedTimestep::from_hz(SIMULATION_HZ),
+	);
+	let timestep = frame_marker.timestep();
 
-	Ok(wrap_animation_body(move |params: AnimationParams| {
-		frame_marker.frame(params);
-	}))
+	let animation_body =
+		wrap_animation_body(move |params: AnimationParams| frame_marker.frame(params));
+	Ok((animation_body, timestep))
 }
@@ -1,4 +1,5 @@
 pub mod animation_loop;
+pub mod offline_render;
 mod pipeline;
 pub mod quad;
 pub mod shaders;
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::render_core::frame_capture::{
+	global_capture, CaptureFormat, CapturedFile, FrameSequenceWriter,
+};
+use crate::render_core::frame_clock::{AnimationFrameClock, FrameClock, ManualFrameClock};
+
+/// Renders frames a fixed time apart, as fast as they can be drawn rather than
+/// in real time, so every run from the same point captures exactly the same
+/// frames.
+pub struct OfflineRender {
+	pub frames: u32,
+	pub delta_time: Duration,
+	pub format: CaptureFormat,
+}
+
+impl OfflineRender {
+	/// Moves the page's animation loop over to a manual clock with
+	/// `switch_clock`, captures each frame, and then hands the loop back to
+	/// the display.
+	///
+	/// The first frame takes no time, as the loop's first on a new clock
+	/// always does, so it's the animation as it stands. Each one after is
+	/// `delta_time` later.
+	pub async fn run(
+		&self,
+		switch_clock: impl Fn(Box<dyn FrameClock>) -> Result<(), String>,
+	) -> Result<Vec<CapturedFile>, String> {
+		let clock = ManualFrameClock::new();
+		switch_clock(Box::new(clock.clone()))?;
+		let files = self.capture_frames(&clock).await;
+		switch_clock(Box::new(AnimationFrameClock::new()))?;
+		files
+	}
+
+	async fn capture_frames(&self, clock: &ManualFrameClock) -> Result<Vec<CapturedFile>, String> {
+		let capture = global_capture();
+		let mut writer = FrameSequenceWriter::new(self.format);
+		for frame in 0..self.frames {
+			let drawn = capture.next_frame();
+			clock.advance(self.delta_time);
+			let image = drawn.await.map_err(|_| format!("Frame {frame} was never drawn"))?;
+			writer
+				.add_frame(&image)
+				.map_err(|err| format!("Failed to encode frame {frame}: {err}"))?;
+		}
+		Ok(writer.finish())
+	}
+}
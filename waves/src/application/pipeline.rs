@@ -4,6 +4,7 @@ use web_sys::WebGl2RenderingContext;
 use crate::application::quad::generate_drawable_quad;
 use crate::application::shaders::ShaderContext;
 use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::frame_capture::FrameCapture;
 use crate::render_core::frame_sequencer::FrameGate;
 use crate::render_core::mesh::{clear_frame, draw_meshes_always, DrawMode};
 use crate::render_core::ping_pong_buffer::PingPongBuffer;
@@ -11,22 +12,11 @@ use crate::render_core::uniform;
 
 pub async fn draw_indirect(
 	gate: FrameGate<AnimationParams>,
-	simulation_frame: async_channel::Receiver<u64>,
 	new_frame_shader: ShaderContext,
 	// combine_frames_shader: ShaderContext,
 	render_to_texture: ShaderContext,
+	capture: FrameCapture,
 ) {
-	let check_frame = async move |current_frame: u64| {
-		let last_simulation_frame =
-			simulation_frame.recv().await.expect("Failed to receive frame number");
-		if last_simulation_frame != current_frame {
-			panic!(
-				"Draw is out of sync with simulation! Expected frame {}, got {}",
-				current_frame, last_simulation_frame
-			);
-		}
-	};
-
 	let clear_color = nglm::vec4(0.0, 0.0, 0.0, 1.0);
 	let meshes_and_buffers = generate_drawable_quad(new_frame_shader.clone());
 
@@ -50,7 +40,6 @@ pub async fn draw_indirect(
 
 	loop {
 		let params = (&gate).await;
-		check_frame(params.frame_number).await;
 
 		let f_dimensions = params.viewport.dimensions();
 		texture_dimensions = nglm::vec2(f_dimensions.x as u32, f_dimensions.y as u32);
@@ -79,6 +68,11 @@ pub async fn draw_indirect(
 			texture_to_draw.smart_write(pingpong.current_texture_index() as i32);
 
 			draw_meshes_always(context, &meshes_and_buffers, DrawMode::Surface);
+			capture.frame_drawn(
+				context,
+				params.viewport.width() as u32,
+				params.viewport.height() as u32,
+			);
 		}
 
 		pingpong.bind_next();
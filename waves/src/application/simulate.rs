@@ -7,13 +7,8 @@ use crate::render_core::animation_params::AnimationParams;
 use crate::render_core::frame_sequencer::FrameGate;
 use crate::render_core::uniform;
 
-pub async fn waves(
-	gate: FrameGate<AnimationParams>,
-	frame_sender: async_channel::Sender<u64>,
-	shader: ShaderContext,
-) {
+pub async fn waves(gate: FrameGate<AnimationParams>, shader: ShaderContext, wavelength: f32) {
 	let mut phase = 0.0f32;
-	let wavelength = 0.1f32;
 	let phase_step_per_sec = TAU;
 
 	shader.use_shader();
@@ -38,12 +33,29 @@ pub async fn waves(
 		let height = params.viewport.height();
 		u_viewport_size.smart_write(nglm::vec2(width, height));
 
-		phase += phase_step_per_sec * params.delta_time.as_secs_f32();
-		if phase > TAU {
-			phase -= TAU;
+		match params.fixed_ticks {
+			Some(ticks) => {
+				// Worked out from the number of ticks run, rather than added up, so
+				// it only depends on where the timestep is (e.g. restored for a
+				// replay)
+				let ticks_run = ticks.ticks().end;
+				let phase_per_tick = phase_step_per_sec as f64 * ticks.step.as_secs_f64();
+				phase = (ticks_run as f64 * phase_per_tick).rem_euclid(TAU as f64) as f32;
+				let previous_phase =
+					if ticks_run == 0 { phase } else { phase - phase_per_tick as f32 };
+				// Drawn somewhere between the last two ticks, so motion stays smooth
+				// when the display and simulation rates don't line up
+				let alpha = ticks.interpolation;
+				u_phase
+					.smart_write(Vec2::repeat(previous_phase + (phase - previous_phase) * alpha));
+			}
+			None => {
+				phase += phase_step_per_sec * params.delta_time.as_secs_f32();
+				if phase > TAU {
+					phase -= TAU;
+				}
+				u_phase.smart_write(Vec2::repeat(phase));
+			}
 		}
-		u_phase.smart_write(Vec2::repeat(phase));
-
-		frame_sender.send(params.frame_number).await.expect("Failed to send frame number");
 	}
 }
@@ -1,15 +1,25 @@
 #![feature(async_closure)]
 #![feature(extern_types)]
 #![feature(future_join)]
-#![feature(trait_alias)]
 extern crate nalgebra_glm as nglm;
 
+use std::cell::{Cell, RefCell};
+
+use single_thread_executor::{new_executor_and_spawner, ShutdownMode};
 use utils::prelude::*;
+use wasm_bindgen_futures::spawn_local;
 use web_sys::WebGl2RenderingContext;
 
-use crate::render_core::animation::run_animation_loop;
+use crate::application::animation_loop::{get_animation_loop, ViewSettings};
+use crate::application::offline_render::OfflineRender;
+use crate::render_core::animation::{run_animation_loop, AnimationLoop, AnimationViews};
+use crate::render_core::animation_controller::global_controller;
+use crate::render_core::canvas::{get_webgl2_canvas, CanvasConfig};
+use crate::render_core::frame_capture::CaptureFormat;
+use crate::render_core::frame_clock::{AnimationFrameClock, FrameClock, IntervalClock};
+use crate::render_core::frame_recorder::global_recorder;
 use crate::render_core::viewport::Viewport;
-use crate::utils::set_panic_hook;
+use crate::utils::{duration_from_millis, set_panic_hook};
 
 #[macro_use]
 mod utils;
@@ -22,24 +32,172 @@ extern "C" {
 	fn remove_overlay();
 }
 
+thread_local! {
+	static ANIMATION_LOOP: RefCell<Option<AnimationLoop>> = const { RefCell::new(None) };
+	static VIEWS: RefCell<Option<AnimationViews>> = const { RefCell::new(None) };
+	/// Set while `renderFrames` has the animation loop on its own clock
+	static RENDERING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn refuse_while_rendering() -> Result<(), JsValue> {
+	if RENDERING.get() {
+		return Err("Frames are being rendered; wait for renderFrames to finish".into());
+	}
+	Ok(())
+}
+
 #[wasm_bindgen(start)]
 pub fn start() -> Result<(), JsValue> {
 	set_panic_hook();
+
+	// Every view's tasks share the one executor
+	let (executor, spawner) = new_executor_and_spawner();
+	spawn_local(async move {
+		executor.run().await;
+	});
+	let views = AnimationViews::new(spawner);
+	attach_to_canvas(
+		&views,
+		&CanvasConfig::default(),
+		ViewSettings { is_main_view: true, ..Default::default() },
+	)?;
+
+	remove_overlay();
+
+	let animation_loop = run_animation_loop(
+		views.clone(),
+		global_controller(),
+		global_recorder(),
+		Box::new(AnimationFrameClock::new()),
+	);
+	ANIMATION_LOOP.with(|cell| cell.replace(Some(animation_loop)));
+	VIEWS.with(|cell| cell.replace(Some(views)));
+
+	Ok(())
+}
+
+fn attach_to_canvas(
+	views: &AnimationViews,
+	canvas_config: &CanvasConfig,
+	settings: ViewSettings,
+) -> Result<(), JsValue> {
 	let (canvas, context) =
-		render_core::canvas::get_webgl2_canvas().ok_or("Failed to create WebGL2 context")?;
+		get_webgl2_canvas(canvas_config).map_err(|err| JsValue::from_str(&err.to_string()))?;
 
 	// Workaround: https://stackoverflow.com/a/18934718/1403459
 	canvas.set_attribute("tabindex", "0")?;
-	canvas.focus()?;
+	if settings.is_main_view {
+		canvas.focus()?;
+	}
 
 	context.enable(WebGl2RenderingContext::DEPTH_TEST);
 	context.depth_func(WebGl2RenderingContext::LESS);
 
-	remove_overlay();
-
 	let viewport = Viewport::new(canvas.clone(), context.clone());
-	let animation_body = application::animation_loop::get_animation_loop(canvas, context)?;
-	run_animation_loop(viewport, animation_body); // Never returns
+	let (animation_body, timestep) =
+		get_animation_loop(views.spawner(), canvas, context, &settings)?;
+	views.add(viewport, animation_body, timestep);
+	Ok(())
+}
+
+/// Adds another view of the waves, on the canvas matching the CSS `selector`,
+/// e.g. to compare wavelengths side by side. Every view runs in step, and
+/// pauses together.
+#[wasm_bindgen(js_name = attachView)]
+pub fn attach_view(selector: &str, wavelength: f32) -> Result<(), JsValue> {
+	let views = VIEWS.with(|cell| cell.borrow().clone()).ok_or("The animation has been stopped")?;
+	attach_to_canvas(
+		&views,
+		&CanvasConfig::new(selector),
+		ViewSettings { wavelength, ..Default::default() },
+	)
+}
 
+/// Stops the animation for good, and frees everything the views were using.
+/// This can't be done while frames are being rendered.
+#[wasm_bindgen(js_name = stopAnimation)]
+pub fn stop_animation() -> Result<(), JsValue> {
+	refuse_while_rendering()?;
+	if let Some(animation_loop) = ANIMATION_LOOP.with(RefCell::take) {
+		animation_loop.stop();
+	}
+	// Gate tasks and their sequencers hold each other (through the wakers the
+	// sequencers keep), so they have to be cancelled to be dropped
+	if let Some(views) = VIEWS.with(RefCell::take) {
+		views.spawner().shutdown(ShutdownMode::Cancel);
+	}
 	Ok(())
 }
+
+/// Runs frames every `interval_millis`, which must be more than zero, instead
+/// of with the display. Passing nothing goes back to following the display.
+/// This can't be changed while frames are being rendered.
+#[wasm_bindgen(js_name = setFrameInterval)]
+pub fn set_frame_interval(interval_millis: Option<f64>) -> Result<(), JsValue> {
+	refuse_while_rendering()?;
+	let clock: Box<dyn FrameClock> = match interval_millis {
+		Some(millis) => {
+			let interval = duration_from_millis(millis)?;
+			if interval.is_zero() {
+				return Err("The frame interval must be more than zero".into());
+			}
+			Box::new(IntervalClock::new(interval))
+		}
+		None => Box::new(AnimationFrameClock::new()),
+	};
+	ANIMATION_LOOP.with(|cell| {
+		if let Some(animation_loop) = cell.borrow_mut().as_mut() {
+			animation_loop.switch_clock(clock);
+		}
+	});
+	Ok(())
+}
+
+/// Renders `frames` frames, `delta_millis` apart, as fast as they can be
+/// drawn, and returns them as numbered PNGs, or a single zip of them. Each
+/// file is a `{ name, contents }` object. The first frame is the animation as
+/// it stands.
+#[wasm_bindgen(js_name = renderFrames)]
+pub async fn render_frames(
+	frames: u32,
+	delta_millis: f64,
+	zip: bool,
+) -> Result<js_sys::Array, JsValue> {
+	refuse_while_rendering()?;
+	let delta_time = duration_from_millis(delta_millis)?;
+	if ANIMATION_LOOP.with(|cell| cell.borrow().is_none()) {
+		return Err("The animation has been stopped".into());
+	}
+	let render = OfflineRender {
+		frames,
+		delta_time,
+		format: if zip { CaptureFormat::Zip } else { CaptureFormat::PngSequence },
+	};
+	RENDERING.set(true);
+	let files = render
+		.run(|clock| {
+			ANIMATION_LOOP.with(|cell| match cell.borrow_mut().as_mut() {
+				Some(animation_loop) => {
+					animation_loop.switch_clock(clock);
+					Ok(())
+				}
+				None => Err("The animation has been stopped".to_string()),
+			})
+		})
+		.await;
+	RENDERING.set(false);
+	let files = files?;
+
+	let array = js_sys::Array::new();
+	for file in files {
+		let object = js_sys::Object::new();
+		js_sys::Reflect::set(&object, &"name".into(), &file.name.into())?;
+		js_sys::Reflect::set(
+			&object,
+			&"contents".into(),
+			&js_sys::Uint8Array::from(file.contents.as_slice()),
+		)?;
+		array.push(&object);
+	}
+	Ok(array)
+}
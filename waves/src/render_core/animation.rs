@@ -1,48 +1,254 @@
-use std::cell::RefCell;
-use std::ops::DerefMut;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::time::Duration;
 
+use single_thread_executor::Spawner;
+
+use crate::render_core::animation_controller::AnimationController;
 use crate::render_core::animation_params::AnimationParams;
-use crate::utils::prelude::*;
+use crate::render_core::fixed_timestep::FixedTimestep;
+use crate::render_core::frame_clock::{FrameCallback, FrameClock};
+use crate::render_core::frame_recorder::{FrameRecorder, RecordedFrame};
+use crate::render_core::frame_timer::{FrameTimer, FrameTimes, VisibilityListener};
 use crate::Viewport;
 
 pub type AnimationFn = Box<dyn FnMut(AnimationParams)>;
 
 pub fn wrap_animation_body<F: 'static + FnMut(AnimationParams)>(f: F) -> AnimationFn { Box::new(f) }
 
-fn window() -> web_sys::Window { web_sys::window().expect("no global `window` exists") }
+/// The views an animation loop draws, each with its own viewport and
+/// animation body. All of their tasks run on the same executor.
+#[derive(Clone)]
+pub struct AnimationViews {
+	spawner: Spawner,
+	views: Rc<RefCell<Vec<View>>>,
+}
+
+struct View {
+	viewport: Viewport,
+	animation_body: AnimationFn,
+	/// What the animation body runs its frames through, so the loop's
+	/// `FrameRecorder` can save and restore it
+	timestep: Rc<RefCell<FixedTimestep>>,
+}
+
+impl AnimationViews {
+	pub fn new(spawner: Spawner) -> Self { Self { spawner, views: Rc::default() } }
+
+	/// The spawner for the executor shared by every view's tasks.
+	pub fn spawner(&self) -> &Spawner { &self.spawner }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-	window()
-		.request_animation_frame(f.as_ref().unchecked_ref())
-		.expect("should register `requestAnimationFrame` OK");
+	/// Adds a view, which is drawn from the loop's next frame on. This can't
+	/// be called from inside a frame.
+	pub fn add(
+		&self,
+		viewport: Viewport,
+		animation_body: AnimationFn,
+		timestep: Rc<RefCell<FixedTimestep>>,
+	) {
+		self.views.borrow_mut().push(View { viewport, animation_body, timestep });
+	}
 }
 
-pub fn run_animation_loop(viewport: Viewport, mut animation_body: AnimationFn) {
-	let next_frame = Rc::new(RefCell::new(None));
-	let start_frame = next_frame.clone();
+/// A running animation loop. Dropping this leaves the loop running.
+pub struct AnimationLoop {
+	clock: Box<dyn FrameClock>,
+	last_frame_time: Rc<Cell<Option<Duration>>>,
+	real_time: Rc<Cell<bool>>,
+}
 
-	let performance = window().performance().expect("performance should be available");
+impl AnimationLoop {
+	/// Stops the loop, dropping its hold on the views, and so their
+	/// `AnimationFn`s if nothing else has hold of them.
+	pub fn stop(mut self) { self.clock.stop(); }
 
-	let last_frame_time = RefCell::new(performance.now());
+	/// Moves the loop over to `clock`, from the next frame on. Clocks' times
+	/// can't be compared, so that frame takes no time, like the loop's first.
+	/// This can't be called from inside one of the loop's own frames.
+	pub fn switch_clock(&mut self, mut clock: Box<dyn FrameClock>) {
+		let on_frame = self.clock.stop().expect("Can't switch clocks from inside a frame");
+		self.last_frame_time.set(None);
+		self.real_time.set(clock.is_real_time());
+		clock.start(on_frame);
+		self.clock = clock;
+	}
+}
+
+/// Runs `on_frame` on each of `clock`'s frames, with the time since the last
+/// one (which is zero for the first), the frame's number, and whether the
+/// clock it came from `is_real_time`.
+fn run_frame_loop(
+	mut clock: Box<dyn FrameClock>,
+	mut on_frame: impl FnMut(Duration, u64, bool) + 'static,
+) -> AnimationLoop {
+	let last_frame_time = Rc::new(Cell::new(None));
+	let real_time = Rc::new(Cell::new(clock.is_real_time()));
 	let mut frame_number = 0;
+	let (loop_last_frame_time, loop_real_time) = (last_frame_time.clone(), real_time.clone());
+	let callback: FrameCallback = Box::new(move |now: Duration| {
+		let last = loop_last_frame_time.replace(Some(now));
+		on_frame(now.saturating_sub(last.unwrap_or(now)), frame_number, loop_real_time.get());
+		frame_number += 1;
+	});
+	clock.start(callback);
+	AnimationLoop { clock, last_frame_time, real_time }
+}
 
-	*start_frame.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-		let this_frame_time = performance.now();
-		let duration_millis: f64 = this_frame_time - last_frame_time.borrow().clone();
-		let duration = Duration::from_micros((duration_millis * 1000.0) as u64);
-		last_frame_time.replace(this_frame_time);
+/// Works out each frame's times, once for all of the views, so they stay in
+/// step.
+struct LoopTiming {
+	frame_timer: FrameTimer,
+	controller: AnimationController,
+}
+
+impl LoopTiming {
+	fn new(controller: AnimationController) -> Self {
+		Self { frame_timer: FrameTimer::default(), controller }
+	}
+
+	fn frame(&mut self, raw_delta_time: Duration, real_time: bool, shown: bool) -> FrameTimes {
+		// Offline renders ask for their frames' times, so they're kept exactly
+		let mut times = if real_time {
+			if shown {
+				self.frame_timer.reset();
+			}
+			self.frame_timer.frame(raw_delta_time)
+		} else {
+			self.frame_timer.exact_frame(raw_delta_time)
+		};
+		times.delta = self.controller.next_delta_time(times.delta);
+		times
+	}
+}
 
-		viewport.on_frame();
-		animation_body.deref_mut()(AnimationParams {
-			viewport: viewport.clone(),
-			delta_time: duration,
+/// Draws every one of `views` on each of `clock`'s frames, paused and stepped
+/// by `controller`, and recorded or replayed by `recorder`.
+pub fn run_animation_loop(
+	views: AnimationViews,
+	controller: AnimationController,
+	recorder: FrameRecorder,
+	clock: Box<dyn FrameClock>,
+) -> AnimationLoop {
+	// Browsers stop sending frames while the page is hidden, and that gap
+	// shouldn't be simulated all at once when it comes back
+	let visibility_listener = VisibilityListener::new();
+	let mut timing = LoopTiming::new(controller);
+
+	run_frame_loop(clock, move |raw_delta_time, frame_number, real_time| {
+		let times =
+			timing.frame(raw_delta_time, real_time, real_time && visibility_listener.take_shown());
+
+		views.spawner.advance_frame();
+		let mut views = views.views.borrow_mut();
+		views.iter().for_each(|view| view.viewport.on_frame());
+		// Once for every view, so they all replay the same frames. Paused frames
+		// are recorded too, so replays pause in the same places.
+		let live = RecordedFrame {
+			delta_time: times.delta,
+			raw_delta_time: times.raw,
+			smoothed_delta_time: times.smoothed,
 			frame_number,
+			sizes: views
+				.iter()
+				.map(|view| (view.viewport.width(), view.viewport.height()))
+				.collect(),
+		};
+		let frame = recorder.process(live, views.iter().map(|view| &*view.timestep));
+		for (index, view) in views.iter_mut().enumerate() {
+			(view.animation_body)(frame.params_for(index, &view.viewport));
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+	use std::time::Duration;
+
+	use crate::render_core::animation::{run_frame_loop, LoopTiming};
+	use crate::render_core::animation_controller::AnimationController;
+	use crate::render_core::frame_clock::ManualFrameClock;
+
+	fn millis(millis: u64) -> Duration { Duration::from_millis(millis) }
+
+	#[test]
+	fn frames_follow_the_clock() {
+		let clock = ManualFrameClock::new();
+		let frames = Rc::new(RefCell::new(Vec::new()));
+		let loop_frames = frames.clone();
+		let _animation_loop =
+			run_frame_loop(Box::new(clock.clone()), move |delta, number, real_time| {
+				// Manual frames come exactly when asked for
+				assert!(!real_time);
+				loop_frames.borrow_mut().push((delta, number))
+			});
+
+		clock.advance(millis(100));
+		clock.advance(millis(16));
+		clock.advance(millis(20));
+		assert_eq!(*frames.borrow(), vec![(millis(0), 0), (millis(16), 1), (millis(20), 2)]);
+	}
+
+	#[test]
+	fn stopping_drops_the_loop() {
+		let clock = ManualFrameClock::new();
+		let frames = Rc::new(RefCell::new(0));
+		let loop_frames = frames.clone();
+		let animation_loop =
+			run_frame_loop(Box::new(clock.clone()), move |_, _, _| *loop_frames.borrow_mut() += 1);
+
+		clock.advance(millis(16));
+		animation_loop.stop();
+		clock.advance(millis(16));
+		assert_eq!(*frames.borrow(), 1);
+		assert_eq!(Rc::strong_count(&frames), 1);
+	}
+
+	#[test]
+	fn switching_clocks_keeps_the_loop_going() {
+		let first = ManualFrameClock::new();
+		let second = ManualFrameClock::new();
+		let frames = Rc::new(RefCell::new(Vec::new()));
+		let loop_frames = frames.clone();
+		let mut animation_loop =
+			run_frame_loop(Box::new(first.clone()), move |delta, number, _| {
+				loop_frames.borrow_mut().push((delta, number))
+			});
+
+		first.advance(millis(100));
+		first.advance(millis(16));
+		animation_loop.switch_clock(Box::new(second.clone()));
+		first.advance(millis(16));
+		// The new clock's times have nothing to do with the old one's
+		second.advance(millis(500));
+		second.advance(millis(20));
+		assert_eq!(
+			*frames.borrow(),
+			vec![(millis(0), 0), (millis(16), 1), (millis(0), 2), (millis(20), 3)]
+		);
+	}
+
+	#[test]
+	fn every_view_gets_the_same_stepped_frames() {
+		let clock = ManualFrameClock::new();
+		let controller = AnimationController::new();
+		let mut timing = LoopTiming::new(controller.clone());
+		let views = Rc::new(RefCell::new(vec![Vec::new(), Vec::new()]));
+		let loop_views = views.clone();
+		let _animation_loop = run_frame_loop(Box::new(clock.clone()), move |raw, _, real_time| {
+			let times = timing.frame(raw, real_time, false);
+			for view in loop_views.borrow_mut().iter_mut() {
+				view.push(times.delta);
+			}
 		});
-		request_animation_frame(next_frame.borrow().as_ref().unwrap());
-		frame_number += 1;
-	}) as Box<dyn FnMut()>));
 
-	request_animation_frame(start_frame.borrow().as_ref().unwrap());
+		controller.step(1, millis(5));
+		clock.advance(millis(16));
+		clock.advance(millis(16));
+		controller.resume();
+		clock.advance(millis(16));
+		let expected = vec![millis(5), Duration::ZERO, millis(16)];
+		assert_eq!(*views.borrow(), vec![expected.clone(), expected]);
+	}
 }
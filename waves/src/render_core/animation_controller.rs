@@ -0,0 +1,126 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::utils::duration_from_millis;
+use crate::utils::prelude::*;
+
+/// Pauses, resumes and single-steps an animation loop.
+///
+/// Frames keep coming while paused, but with no time passing, so everything
+/// is still drawn (e.g. after a resize) while the simulation stands still.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct AnimationController {
+	state: Rc<ControllerState>,
+}
+
+#[derive(Default)]
+struct ControllerState {
+	paused: Cell<bool>,
+	steps_left: Cell<u32>,
+	step_delta: Cell<Duration>,
+}
+
+thread_local! {
+	static GLOBAL_CONTROLLER: AnimationController = AnimationController::new();
+}
+
+/// The controller for the page's animation loop, which is also what
+/// JavaScript gets from `animationController()`.
+#[wasm_bindgen(js_name = animationController)]
+pub fn global_controller() -> AnimationController {
+	GLOBAL_CONTROLLER.with(AnimationController::clone)
+}
+
+impl AnimationController {
+	pub fn new() -> Self { Self::default() }
+
+	/// Pauses the animation, then runs the next `frames` frames as if
+	/// `delta_time` passed in each of them. Steps add up if called again
+	/// before they've run.
+	pub fn step(&self, frames: u32, delta_time: Duration) {
+		self.pause();
+		self.state.steps_left.set(self.state.steps_left.get() + frames);
+		self.state.step_delta.set(delta_time);
+	}
+
+	/// The time to move the animation on by this frame, given how much really
+	/// passed. This should be asked once per frame, however many views the
+	/// loop draws, since each call uses up a step.
+	pub fn next_delta_time(&self, real_delta_time: Duration) -> Duration {
+		let state = &self.state;
+		if !state.paused.get() {
+			real_delta_time
+		} else if state.steps_left.get() > 0 {
+			state.steps_left.set(state.steps_left.get() - 1);
+			state.step_delta.get()
+		} else {
+			Duration::ZERO
+		}
+	}
+}
+
+#[wasm_bindgen]
+impl AnimationController {
+	pub fn pause(&self) { self.state.paused.set(true); }
+
+	/// Resumes the animation, dropping any steps that haven't run yet.
+	pub fn resume(&self) {
+		self.state.paused.set(false);
+		self.state.steps_left.set(0);
+	}
+
+	#[wasm_bindgen(js_name = isPaused)]
+	pub fn is_paused(&self) -> bool { self.state.paused.get() }
+
+	/// `step`, for JavaScript, with the delta in milliseconds. Negative and
+	/// non-finite deltas are refused.
+	#[wasm_bindgen(js_name = step)]
+	pub fn step_millis(&self, frames: u32, delta_millis: f64) -> Result<(), JsValue> {
+		self.step(frames, duration_from_millis(delta_millis)?);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use crate::render_core::animation_controller::AnimationController;
+
+	const FRAME: Duration = Duration::from_millis(16);
+
+	#[test]
+	fn paused_frames_have_no_delta_time() {
+		let controller = AnimationController::new();
+		assert_eq!(controller.next_delta_time(FRAME), FRAME);
+
+		controller.pause();
+		assert_eq!(controller.next_delta_time(FRAME), Duration::ZERO);
+
+		controller.resume();
+		assert_eq!(controller.next_delta_time(FRAME), FRAME);
+	}
+
+	#[test]
+	fn step_runs_the_given_number_of_frames() {
+		let controller = AnimationController::new();
+		let step = Duration::from_millis(5);
+		controller.step(2, step);
+		assert!(controller.is_paused());
+
+		assert_eq!(controller.next_delta_time(FRAME), step);
+		assert_eq!(controller.next_delta_time(FRAME), step);
+		assert_eq!(controller.next_delta_time(FRAME), Duration::ZERO);
+	}
+
+	#[test]
+	fn resume_drops_remaining_steps() {
+		let controller = AnimationController::new();
+		controller.step(3, Duration::from_millis(5));
+		controller.resume();
+		controller.pause();
+		assert_eq!(controller.next_delta_time(FRAME), Duration::ZERO);
+	}
+}
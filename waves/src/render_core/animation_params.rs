@@ -1,10 +1,37 @@
 use std::time::Duration;
 
+use crate::render_core::fixed_timestep::FixedTicks;
+use crate::render_core::frame_sequencer::FrameParams;
 use crate::render_core::viewport::Viewport;
 
 #[derive(Clone)]
 pub struct AnimationParams {
 	pub viewport: Viewport,
+	/// The time to advance the animation by, which is the time since the last
+	/// frame, unless that's been cut short by a `FrameTimer`.
 	pub delta_time: Duration,
+	/// The time since the last frame, as measured
+	pub raw_delta_time: Duration,
+	/// A moving average of `delta_time`
+	pub smoothed_delta_time: Duration,
 	pub frame_number: u64,
+	/// Filled in by a `FixedTimestepMarker`, if the frames go through one
+	pub fixed_ticks: Option<FixedTicks>,
+	/// How many frames before this one the gate skipped, because of its
+	/// `GateRate`. Their time and ticks are included in this frame's.
+	pub skipped_frames: u64,
+}
+
+impl FrameParams for AnimationParams {
+	fn elapsed(&self) -> Duration { self.delta_time }
+
+	fn include_skipped(&mut self, earlier: &Self) {
+		self.delta_time += earlier.delta_time;
+		self.raw_delta_time += earlier.raw_delta_time;
+		self.skipped_frames += earlier.skipped_frames + 1;
+		if let (Some(ticks), Some(earlier_ticks)) = (&mut self.fixed_ticks, earlier.fixed_ticks) {
+			ticks.first_tick = earlier_ticks.first_tick;
+			ticks.count += earlier_ticks.count;
+		}
+	}
 }
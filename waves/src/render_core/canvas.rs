@@ -1,17 +1,114 @@
+use std::fmt;
+
 use utils::prelude::*;
-use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
+use web_sys::{
+	HtmlCanvasElement, WebGl2RenderingContext, WebGlContextAttributes, WebGlPowerPreference,
+};
 
 use crate::utils;
 
-pub fn get_webgl2_canvas() -> Option<(HtmlCanvasElement, WebGl2RenderingContext)> {
-	let document = web_sys::window().unwrap().document().unwrap();
-	let canvas = document.get_element_by_id("render_canvas").unwrap();
-	let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().ok()?;
+/// Which canvas to draw to, and how its WebGL2 context should be set up. The
+/// defaults are WebGL's own.
+#[derive(Clone, Debug)]
+pub struct CanvasConfig {
+	/// A CSS selector for the canvas, e.g. `#render_canvas`
+	pub selector: String,
+	pub alpha: bool,
+	pub antialias: bool,
+	pub depth: bool,
+	pub stencil: bool,
+	/// Keeps what was drawn after it's been shown, rather than clearing it,
+	/// so it can still be read back later
+	pub preserve_drawing_buffer: bool,
+	pub premultiplied_alpha: bool,
+	pub power_preference: WebGlPowerPreference,
+}
+
+impl CanvasConfig {
+	pub fn new(selector: &str) -> Self {
+		Self {
+			selector: selector.to_string(),
+			alpha: true,
+			antialias: true,
+			depth: true,
+			stencil: false,
+			preserve_drawing_buffer: false,
+			premultiplied_alpha: true,
+			power_preference: WebGlPowerPreference::Default,
+		}
+	}
+
+	fn context_attributes(&self) -> WebGlContextAttributes {
+		let mut attributes = WebGlContextAttributes::new();
+		attributes
+			.alpha(self.alpha)
+			.antialias(self.antialias)
+			.depth(self.depth)
+			.stencil(self.stencil)
+			.preserve_drawing_buffer(self.preserve_drawing_buffer)
+			.premultiplied_alpha(self.premultiplied_alpha)
+			.power_preference(self.power_preference);
+		attributes
+	}
+}
+
+impl Default for CanvasConfig {
+	fn default() -> Self { Self::new("#render_canvas") }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanvasError {
+	InvalidSelector(String),
+	NotFound(String),
+	/// The selector matched an element, but it isn't a canvas.
+	NotACanvas(String),
+	/// The browser doesn't support WebGL2, or the canvas already has a
+	/// different kind of context.
+	NoWebGl2(String),
+}
+
+impl fmt::Display for CanvasError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CanvasError::InvalidSelector(selector) => {
+				write!(f, "'{selector}' is not a valid CSS selector")
+			}
+			CanvasError::NotFound(selector) => write!(f, "No element matches '{selector}'"),
+			CanvasError::NotACanvas(selector) => {
+				write!(f, "The element matching '{selector}' is not a canvas")
+			}
+			CanvasError::NoWebGl2(selector) => write!(
+				f,
+				"Failed to create a WebGL2 context on '{selector}': WebGL2 isn't supported, or \
+				 the canvas already has another kind of context"
+			),
+		}
+	}
+}
+
+impl std::error::Error for CanvasError {}
+
+pub fn get_webgl2_canvas(
+	config: &CanvasConfig,
+) -> Result<(HtmlCanvasElement, WebGl2RenderingContext), CanvasError> {
+	let selector = &config.selector;
+	let document = window().document().expect("window should have a document");
+	let canvas = document
+		.query_selector(selector)
+		.map_err(|_| CanvasError::InvalidSelector(selector.clone()))?
+		.ok_or_else(|| CanvasError::NotFound(selector.clone()))?;
+	let canvas: HtmlCanvasElement = canvas
+		.dyn_into::<HtmlCanvasElement>()
+		.map_err(|_| CanvasError::NotACanvas(selector.clone()))?;
 
-	let context: WebGl2RenderingContext =
-		canvas.get_context("webgl2").ok()?.unwrap().dyn_into::<WebGl2RenderingContext>().ok()?;
+	let context = canvas
+		.get_context_with_context_options("webgl2", &config.context_attributes())
+		.ok()
+		.flatten()
+		.and_then(|context| context.dyn_into::<WebGl2RenderingContext>().ok())
+		.ok_or_else(|| CanvasError::NoWebGl2(selector.clone()))?;
 
-	Some((canvas, context))
+	Ok((canvas, context))
 }
 
 pub fn update_canvas_size(canvas: &HtmlCanvasElement) -> (u32, u32) {
@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::frame_sequencer::FrameMarker;
+
+/// Splits variable frame times into whole, fixed-length simulation ticks, so
+/// the simulation behaves the same regardless of refresh rate or jitter.
+///
+/// Time that doesn't add up to a whole tick carries over to the next frame,
+/// and the fraction of a tick it represents is reported as the interpolation
+/// factor, for blending between the last two simulated states when drawing.
+pub struct FixedTimestep {
+	step: Duration,
+	accumulator: Duration,
+	next_tick: u64,
+}
+
+/// The simulation ticks to run for one rendered frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTicks {
+	pub first_tick: u64,
+	pub count: u32,
+	pub step: Duration,
+	/// How far the frame is between the last tick and the next one, from 0
+	/// to 1
+	pub interpolation: f32,
+}
+
+/// Where a `FixedTimestep` has got to, so a run can be picked up from the same
+/// point later.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimestepState {
+	pub accumulator: Duration,
+	pub next_tick: u64,
+}
+
+impl FixedTicks {
+	pub fn ticks(&self) -> Range<u64> { self.first_tick..self.first_tick + self.count as u64 }
+}
+
+impl FixedTimestep {
+	/// A frame longer than this many ticks (e.g. after the tab was in the
+	/// background) has the rest of its time dropped, rather than stalling the
+	/// page while the simulation catches up.
+	const MAX_TICKS_PER_FRAME: u32 = 8;
+
+	pub fn new(step: Duration) -> Self {
+		assert!(!step.is_zero(), "Fixed timestep must be non-zero");
+		Self { step, accumulator: Duration::ZERO, next_tick: 0 }
+	}
+
+	pub fn from_hz(hz: f64) -> Self { Self::new(Duration::from_secs_f64(1.0 / hz)) }
+
+	pub fn state(&self) -> TimestepState {
+		TimestepState { accumulator: self.accumulator, next_tick: self.next_tick }
+	}
+
+	pub fn restore(&mut self, state: TimestepState) {
+		self.accumulator = state.accumulator;
+		self.next_tick = state.next_tick;
+	}
+
+	/// Adds a frame's worth of time, returning the ticks it completes.
+	pub fn advance(&mut self, delta_time: Duration) -> FixedTicks {
+		self.accumulator += delta_time;
+
+		let mut count = 0;
+		while self.accumulator >= self.step && count < Self::MAX_TICKS_PER_FRAME {
+			self.accumulator -= self.step;
+			count += 1;
+		}
+		if self.accumulator >= self.step {
+			// Capped, so drop the whole ticks left over but keep the fraction
+			let remainder = self.accumulator.as_nanos() % self.step.as_nanos();
+			self.accumulator = Duration::from_nanos(remainder as u64);
+		}
+
+		let first_tick = self.next_tick;
+		self.next_tick += count as u64;
+		FixedTicks {
+			first_tick,
+			count,
+			step: self.step,
+			interpolation: (self.accumulator.as_secs_f64() / self.step.as_secs_f64()).min(1.0)
+				as f32,
+		}
+	}
+}
+
+/// A `FrameMarker` which runs each frame's time through a `FixedTimestep`,
+/// filling in `AnimationParams::fixed_ticks` before the gates see it.
+pub struct FixedTimestepMarker {
+	marker: FrameMarker<AnimationParams>,
+	timestep: Rc<RefCell<FixedTimestep>>,
+}
+
+impl FixedTimestepMarker {
+	pub fn new(marker: FrameMarker<AnimationParams>, timestep: FixedTimestep) -> Self {
+		Self { marker, timestep: Rc::new(RefCell::new(timestep)) }
+	}
+
+	/// The timestep itself, e.g. for a `FrameRecorder` to save and restore.
+	pub fn timestep(&self) -> Rc<RefCell<FixedTimestep>> { self.timestep.clone() }
+
+	pub fn frame(&self, mut params: AnimationParams) {
+		params.fixed_ticks = Some(self.timestep.borrow_mut().advance(params.delta_time));
+		self.marker.frame(params);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use crate::render_core::fixed_timestep::FixedTimestep;
+
+	fn millis(millis: u64) -> Duration { Duration::from_millis(millis) }
+
+	#[test]
+	fn ticks_are_independent_of_frame_rate() {
+		let mut at_60_hz = FixedTimestep::new(millis(5));
+		let mut at_20_hz = FixedTimestep::new(millis(5));
+
+		let fast: u32 = (0..6).map(|_| at_60_hz.advance(millis(10)).count).sum();
+		let slow: u32 = (0..2).map(|_| at_20_hz.advance(millis(30)).count).sum();
+		assert_eq!(fast, 12);
+		assert_eq!(slow, 12);
+	}
+
+	#[test]
+	fn leftover_time_carries_over() {
+		let mut timestep = FixedTimestep::new(millis(10));
+
+		let first = timestep.advance(millis(4));
+		assert_eq!(first.count, 0);
+		assert_eq!(first.interpolation, 0.4);
+
+		let second = timestep.advance(millis(18));
+		assert_eq!(second.count, 2);
+		assert_eq!(second.ticks(), 0..2);
+		assert!((second.interpolation - 0.2).abs() < 1e-6);
+
+		assert_eq!(timestep.advance(millis(10)).ticks(), 2..3);
+	}
+
+	#[test]
+	fn long_frames_are_capped() {
+		let mut timestep = FixedTimestep::new(millis(10));
+
+		let ticks = timestep.advance(Duration::from_secs(5) + millis(4));
+		assert_eq!(ticks.count, FixedTimestep::MAX_TICKS_PER_FRAME);
+		// The backlog is dropped, rather than spread over the next frames
+		let next = timestep.advance(Duration::ZERO);
+		assert_eq!(next.count, 0);
+		assert_eq!(next.interpolation, 0.4);
+	}
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use image::{imageops, ImageFormat, RgbaImage};
+use single_thread_executor::sync::oneshot;
+use web_sys::WebGl2RenderingContext;
+
+use crate::render_core::zip_archive::ZipArchive;
+
+/// Reads frames back from the canvas, when asked for them.
+#[derive(Clone, Default)]
+pub struct FrameCapture {
+	request: Rc<RefCell<Option<oneshot::Sender<RgbaImage>>>>,
+}
+
+thread_local! {
+	static GLOBAL_CAPTURE: FrameCapture = FrameCapture::default();
+}
+
+/// The capture for the page's canvas.
+pub fn global_capture() -> FrameCapture { GLOBAL_CAPTURE.with(FrameCapture::clone) }
+
+impl FrameCapture {
+	/// Asks for the next frame drawn. Only the latest request is answered.
+	pub fn next_frame(&self) -> oneshot::Receiver<RgbaImage> {
+		let (sender, receiver) = oneshot::channel();
+		self.request.replace(Some(sender));
+		receiver
+	}
+
+	/// Called once a frame has been drawn to the canvas, in the same task, as
+	/// the browser may clear the canvas as soon as it's shown.
+	pub fn frame_drawn(&self, context: &WebGl2RenderingContext, width: u32, height: u32) {
+		let Some(request) = self.request.take() else {
+			return;
+		};
+		let mut pixels = vec![0u8; width as usize * height as usize * 4];
+		context
+			.read_pixels_with_opt_u8_array(
+				0,
+				0,
+				width as i32,
+				height as i32,
+				WebGl2RenderingContext::RGBA,
+				WebGl2RenderingContext::UNSIGNED_BYTE,
+				Some(&mut pixels),
+			)
+			.expect("should read pixels OK");
+		let mut image = RgbaImage::from_raw(width, height, pixels).unwrap();
+		// GL's rows start at the bottom
+		imageops::flip_vertical_in_place(&mut image);
+		let _ = request.send(image);
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+	/// One numbered PNG per frame
+	PngSequence,
+	/// The same PNGs, in a single zip file
+	Zip,
+}
+
+/// A finished file, ready to be saved.
+pub struct CapturedFile {
+	pub name: String,
+	pub contents: Vec<u8>,
+}
+
+/// Encodes frames as PNGs, numbered in the order they're added.
+pub struct FrameSequenceWriter {
+	format: CaptureFormat,
+	files: Vec<CapturedFile>,
+}
+
+impl FrameSequenceWriter {
+	pub fn new(format: CaptureFormat) -> Self { Self { format, files: Vec::new() } }
+
+	pub fn add_frame(&mut self, image: &RgbaImage) -> Result<(), image::ImageError> {
+		let mut contents = Vec::new();
+		image.write_to(&mut Cursor::new(&mut contents), ImageFormat::Png)?;
+		let name = format!("frame_{:05}.png", self.files.len());
+		self.files.push(CapturedFile { name, contents });
+		Ok(())
+	}
+
+	pub fn finish(self) -> Vec<CapturedFile> {
+		match self.format {
+			CaptureFormat::PngSequence => self.files,
+			CaptureFormat::Zip => {
+				let mut archive = ZipArchive::new();
+				for file in &self.files {
+					archive.add_file(&file.name, &file.contents);
+				}
+				vec![CapturedFile { name: "frames.zip".to_owned(), contents: archive.finish() }]
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{Rgba, RgbaImage};
+
+	use crate::render_core::frame_capture::{CaptureFormat, FrameSequenceWriter};
+
+	fn frames(format: CaptureFormat) -> FrameSequenceWriter {
+		let mut writer = FrameSequenceWriter::new(format);
+		for shade in [0, 128, 255] {
+			writer.add_frame(&RgbaImage::from_pixel(4, 2, Rgba([shade, 0, 0, 255]))).unwrap();
+		}
+		writer
+	}
+
+	#[test]
+	fn png_sequence_is_numbered_and_decodes() {
+		let files = frames(CaptureFormat::PngSequence).finish();
+		let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+		assert_eq!(names, ["frame_00000.png", "frame_00001.png", "frame_00002.png"]);
+
+		let image = image::load_from_memory(&files[1].contents).unwrap().to_rgba8();
+		assert_eq!(image.dimensions(), (4, 2));
+		assert_eq!(*image.get_pixel(3, 1), Rgba([128, 0, 0, 255]));
+	}
+
+	#[test]
+	fn zip_holds_the_whole_sequence() {
+		let pngs = frames(CaptureFormat::PngSequence).finish();
+		let files = frames(CaptureFormat::Zip).finish();
+		assert_eq!(files.len(), 1);
+		assert_eq!(files[0].name, "frames.zip");
+
+		let zip = &files[0].contents;
+		let first_png = &zip[30 + 15..30 + 15 + pngs[0].contents.len()];
+		assert_eq!(first_png, pngs[0].contents.as_slice());
+	}
+}
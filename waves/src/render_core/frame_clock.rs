@@ -0,0 +1,199 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::utils::prelude::*;
+
+/// Called once per frame, with the time the frame started.
+pub type FrameCallback = Box<dyn FnMut(Duration)>;
+
+/// Decides when an animation loop's frames happen.
+pub trait FrameClock {
+	/// Starts calling `on_frame` once per frame.
+	fn start(&mut self, on_frame: FrameCallback);
+
+	/// Stops calling the frame callback, and hands it back. Returns `None` if
+	/// the clock wasn't started, or if it's stopped from inside the callback,
+	/// in which case the callback is dropped once it returns.
+	fn stop(&mut self) -> Option<FrameCallback>;
+
+	/// Whether frames come in real time, and so can hitch or stall, rather
+	/// than whenever they're asked for.
+	fn is_real_time(&self) -> bool { true }
+}
+
+/// Holds a clock's frame callback, so it can be stopped even while it's
+/// running.
+#[derive(Default)]
+struct CallbackSlot {
+	on_frame: RefCell<Option<FrameCallback>>,
+	running: Cell<bool>,
+}
+
+impl CallbackSlot {
+	fn start(&self, on_frame: FrameCallback) {
+		self.on_frame.replace(Some(on_frame));
+		self.running.set(true);
+	}
+
+	/// Calls the callback, returning whether the clock is still running
+	/// afterwards.
+	fn call(&self, now: Duration) -> bool {
+		let Some(mut on_frame) = self.on_frame.take() else {
+			return false;
+		};
+		on_frame(now);
+		if self.running.get() {
+			self.on_frame.replace(Some(on_frame));
+		}
+		self.running.get()
+	}
+
+	fn stop(&self) -> Option<FrameCallback> {
+		self.running.set(false);
+		self.on_frame.take()
+	}
+}
+
+fn window() -> web_sys::Window { web_sys::window().expect("no global `window` exists") }
+
+fn from_millis(millis: f64) -> Duration { Duration::from_secs_f64(millis.max(0.0) / 1000.0) }
+
+/// Runs frames with `requestAnimationFrame`, i.e. in step with the display,
+/// and not at all while the tab is hidden.
+#[derive(Default)]
+pub struct AnimationFrameClock {
+	state: Rc<AnimationFrameState>,
+}
+
+/// `requestAnimationFrame`'s callback, which is passed the frame's timestamp
+type AnimationFrameClosure = Closure<dyn FnMut(f64)>;
+
+#[derive(Default)]
+struct AnimationFrameState {
+	slot: CallbackSlot,
+	closure: RefCell<Option<AnimationFrameClosure>>,
+	request_id: Cell<Option<i32>>,
+}
+
+impl AnimationFrameState {
+	fn request_frame(&self) {
+		let closure = self.closure.borrow();
+		let closure = closure.as_ref().expect("Requested a frame without a callback");
+		let request_id = window()
+			.request_animation_frame(closure.as_ref().unchecked_ref())
+			.expect("should register `requestAnimationFrame` OK");
+		self.request_id.set(Some(request_id));
+	}
+}
+
+impl AnimationFrameClock {
+	pub fn new() -> Self { Self::default() }
+}
+
+impl FrameClock for AnimationFrameClock {
+	fn start(&mut self, on_frame: FrameCallback) {
+		self.state.slot.start(on_frame);
+		// The closure keeps the state alive, so frames keep coming until stopped
+		// even if the clock itself is dropped
+		let state = self.state.clone();
+		self.state.closure.replace(Some(Closure::new(move |timestamp: f64| {
+			state.request_id.set(None);
+			if state.slot.call(from_millis(timestamp)) {
+				state.request_frame();
+			}
+		})));
+		self.state.request_frame();
+	}
+
+	fn stop(&mut self) -> Option<FrameCallback> {
+		if let Some(request_id) = self.state.request_id.take() {
+			window().cancel_animation_frame(request_id).expect("should cancel animation frame OK");
+		}
+		self.state.closure.take();
+		self.state.slot.stop()
+	}
+}
+
+/// Runs frames on a fixed `setInterval` timer, regardless of the display's
+/// refresh rate. Browsers throttle it in background tabs, but don't stop it.
+pub struct IntervalClock {
+	interval: Duration,
+	state: Rc<IntervalState>,
+}
+
+#[derive(Default)]
+struct IntervalState {
+	slot: CallbackSlot,
+	closure: RefCell<Option<Closure<dyn FnMut()>>>,
+	interval_id: Cell<Option<i32>>,
+}
+
+impl IntervalClock {
+	pub fn new(interval: Duration) -> Self {
+		assert!(!interval.is_zero(), "Frame interval must be non-zero");
+		Self { interval, state: Rc::default() }
+	}
+}
+
+impl FrameClock for IntervalClock {
+	fn start(&mut self, on_frame: FrameCallback) {
+		self.state.slot.start(on_frame);
+		let performance = window().performance().expect("performance should be available");
+		let state = self.state.clone();
+		let closure = Closure::new(move || {
+			if !state.slot.call(from_millis(performance.now())) {
+				if let Some(interval_id) = state.interval_id.take() {
+					window().clear_interval_with_handle(interval_id);
+				}
+			}
+		});
+		let interval_id = window()
+			.set_interval_with_callback_and_timeout_and_arguments_0(
+				closure.as_ref().unchecked_ref(),
+				self.interval.as_millis() as i32,
+			)
+			.expect("should register `setInterval` OK");
+		self.state.closure.replace(Some(closure));
+		self.state.interval_id.set(Some(interval_id));
+	}
+
+	fn stop(&mut self) -> Option<FrameCallback> {
+		if let Some(interval_id) = self.state.interval_id.take() {
+			window().clear_interval_with_handle(interval_id);
+		}
+		self.state.closure.take();
+		self.state.slot.stop()
+	}
+}
+
+/// Runs a frame whenever it's told to, e.g. for tests or offline rendering.
+/// Clones share the same clock.
+#[derive(Clone, Default)]
+pub struct ManualFrameClock {
+	state: Rc<ManualState>,
+}
+
+#[derive(Default)]
+struct ManualState {
+	slot: CallbackSlot,
+	now: Cell<Duration>,
+}
+
+impl ManualFrameClock {
+	pub fn new() -> Self { Self::default() }
+
+	/// Moves the clock on by `by`, and runs a frame at the new time.
+	pub fn advance(&self, by: Duration) {
+		self.state.now.set(self.state.now.get() + by);
+		self.state.slot.call(self.state.now.get());
+	}
+}
+
+impl FrameClock for ManualFrameClock {
+	fn start(&mut self, on_frame: FrameCallback) { self.state.slot.start(on_frame); }
+
+	fn stop(&mut self) -> Option<FrameCallback> { self.state.slot.stop() }
+
+	fn is_real_time(&self) -> bool { false }
+}
@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Write};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::utils::prelude::*;
+
+/// One gate's work on one frame, from the gate opening until its
+/// `FrameContext` is dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GateSpan {
+	pub gate_id: usize,
+	pub gate: String,
+	pub frame: u64,
+	pub start: Duration,
+	pub duration: Duration,
+}
+
+/// Records how long each `FrameGate` takes over its frames, keeping the spans
+/// of the last few frames and a rolling histogram per gate.
+pub struct FrameProfiler {
+	frames_kept: usize,
+	latest_frame: u64,
+	spans: VecDeque<GateSpan>,
+	histograms: BTreeMap<String, GateHistogram>,
+}
+
+impl FrameProfiler {
+	/// Two seconds' worth at 60Hz
+	pub const DEFAULT_FRAMES_KEPT: usize = 120;
+
+	pub fn new(frames_kept: usize) -> Self {
+		assert!(frames_kept > 0, "Profiler has to keep at least one frame");
+		Self { frames_kept, latest_frame: 0, spans: VecDeque::new(), histograms: BTreeMap::new() }
+	}
+
+	pub fn record(&mut self, span: GateSpan) {
+		self.histograms
+			.entry(span.gate.clone())
+			.or_insert_with(|| GateHistogram::new(self.frames_kept))
+			.record(span.duration);
+
+		self.latest_frame = self.latest_frame.max(span.frame);
+		self.spans.push_back(span);
+		let oldest_kept = (self.latest_frame + 1).saturating_sub(self.frames_kept as u64);
+		self.spans.retain(|span| span.frame >= oldest_kept);
+	}
+
+	pub fn spans(&self) -> impl Iterator<Item = &GateSpan> { self.spans.iter() }
+
+	pub fn histogram(&self, gate: &str) -> Option<&GateHistogram> { self.histograms.get(gate) }
+
+	/// The spans kept, in Chrome's Trace Event format, which can be loaded
+	/// into `chrome://tracing` or Perfetto. Each gate gets its own track.
+	pub fn chrome_trace(&self) -> String {
+		let mut spans: Vec<&GateSpan> = self.spans.iter().collect();
+		spans.sort_by_key(|span| (span.start, span.gate_id));
+		let gates: BTreeMap<usize, &str> =
+			spans.iter().map(|span| (span.gate_id, span.gate.as_str())).collect();
+
+		let mut events = Vec::new();
+		for (gate_id, gate) in gates {
+			events.push(format!(
+				r#"{{"name":"thread_name","ph":"M","pid":1,"tid":{gate_id},"args":{{"name":{}}}}}"#,
+				json_string(gate)
+			));
+		}
+		for span in spans {
+			events.push(format!(
+				r#"{{"name":{},"cat":"frame","ph":"X","ts":{:.3},"dur":{:.3},"pid":1,"tid":{},"args":{{"frame":{}}}}}"#,
+				json_string(&span.gate),
+				span.start.as_secs_f64() * 1e6,
+				span.duration.as_secs_f64() * 1e6,
+				span.gate_id,
+				span.frame
+			));
+		}
+		format!(r#"{{"traceEvents":[{}],"displayTimeUnit":"ms"}}"#, events.join(","))
+	}
+}
+
+impl Default for FrameProfiler {
+	fn default() -> Self { Self::new(Self::DEFAULT_FRAMES_KEPT) }
+}
+
+impl fmt::Display for FrameProfiler {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (gate, histogram) in &self.histograms {
+			writeln!(f, "{gate}: {histogram}")?;
+		}
+		Ok(())
+	}
+}
+
+/// The durations of a gate's most recent frames.
+pub struct GateHistogram {
+	window: usize,
+	samples: VecDeque<Duration>,
+	bucket_counts: [usize; Self::BUCKET_BOUNDS.len() + 1],
+}
+
+impl GateHistogram {
+	/// The upper bounds of each bucket, with one more bucket for anything
+	/// slower than the last bound.
+	const BUCKET_BOUNDS: [Duration; 8] = [
+		Duration::from_micros(250),
+		Duration::from_micros(500),
+		Duration::from_millis(1),
+		Duration::from_millis(2),
+		Duration::from_millis(4),
+		Duration::from_millis(8),
+		Duration::from_millis(16),
+		Duration::from_millis(32),
+	];
+
+	fn new(window: usize) -> Self {
+		Self { window, samples: VecDeque::new(), bucket_counts: Default::default() }
+	}
+
+	fn record(&mut self, duration: Duration) {
+		if self.samples.len() == self.window {
+			let oldest = self.samples.pop_front().unwrap();
+			self.bucket_counts[Self::bucket(oldest)] -= 1;
+		}
+		self.samples.push_back(duration);
+		self.bucket_counts[Self::bucket(duration)] += 1;
+	}
+
+	fn bucket(duration: Duration) -> usize {
+		Self::BUCKET_BOUNDS.partition_point(|bound| *bound < duration)
+	}
+
+	pub fn count(&self) -> usize { self.samples.len() }
+
+	pub fn mean(&self) -> Duration {
+		match self.samples.len() {
+			0 => Duration::ZERO,
+			count => self.samples.iter().sum::<Duration>() / count as u32,
+		}
+	}
+
+	pub fn max(&self) -> Duration { self.samples.iter().copied().max().unwrap_or_default() }
+
+	/// The duration which `fraction` (from 0 to 1) of the samples are no
+	/// slower than.
+	pub fn percentile(&self, fraction: f64) -> Duration {
+		let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+		sorted.sort_unstable();
+		let rank = (fraction.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+		sorted.get(rank.saturating_sub(1)).copied().unwrap_or_default()
+	}
+
+	/// Each bucket's upper bound (`None` for the last, unbounded one), and how
+	/// many samples fall into it.
+	pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, usize)> + '_ {
+		Self::BUCKET_BOUNDS
+			.iter()
+			.copied()
+			.map(Some)
+			.chain([None])
+			.zip(self.bucket_counts.iter().copied())
+	}
+}
+
+impl fmt::Display for GateHistogram {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} frames, mean {:?}, p95 {:?}, max {:?} |",
+			self.count(),
+			self.mean(),
+			self.percentile(0.95),
+			self.max()
+		)?;
+		for (bound, count) in self.buckets().filter(|(_bound, count)| *count > 0) {
+			match bound {
+				Some(bound) => write!(f, " <={bound:?}: {count}")?,
+				None => write!(f, " slower: {count}")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+fn json_string(value: &str) -> String {
+	let mut json = String::with_capacity(value.len() + 2);
+	json.push('"');
+	for c in value.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+			c => json.push(c),
+		}
+	}
+	json.push('"');
+	json
+}
+
+thread_local! {
+	static PAGE_PROFILER: RefCell<Option<Rc<RefCell<FrameProfiler>>>> = const { RefCell::new(None) };
+}
+
+/// Makes `profiler` the one JavaScript reads through `frameTrace()` and
+/// `frameTimings()`.
+pub fn set_page_profiler(profiler: Rc<RefCell<FrameProfiler>>) {
+	PAGE_PROFILER.with(|page_profiler| page_profiler.replace(Some(profiler)));
+}
+
+fn with_page_profiler<R>(f: impl FnOnce(&FrameProfiler) -> R) -> Option<R> {
+	PAGE_PROFILER.with(|page_profiler| page_profiler.borrow().as_ref().map(|p| f(&p.borrow())))
+}
+
+/// The page's recent frames as Chrome trace JSON, ready to save to a file.
+#[wasm_bindgen(js_name = frameTrace)]
+pub fn page_frame_trace() -> Option<String> { with_page_profiler(FrameProfiler::chrome_trace) }
+
+/// A summary of how long each gate has been taking.
+#[wasm_bindgen(js_name = frameTimings)]
+pub fn page_frame_timings() -> Option<String> { with_page_profiler(ToString::to_string) }
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use crate::render_core::frame_profiler::{FrameProfiler, GateSpan};
+
+	fn span(gate_id: usize, gate: &str, frame: u64, start_ms: u64, duration_ms: u64) -> GateSpan {
+		GateSpan {
+			gate_id,
+			gate: gate.to_owned(),
+			frame,
+			start: Duration::from_millis(start_ms),
+			duration: Duration::from_millis(duration_ms),
+		}
+	}
+
+	#[test]
+	fn chrome_trace_lists_spans_by_start_time() {
+		let mut profiler = FrameProfiler::new(10);
+		profiler.record(span(1, "Draw", 0, 3, 2));
+		profiler.record(span(0, "Simulate \"Waves\"", 0, 1, 2));
+
+		assert_eq!(
+			profiler.chrome_trace(),
+			concat!(
+				r#"{"traceEvents":["#,
+				r#"{"name":"thread_name","ph":"M","pid":1,"tid":0,"args":{"name":"Simulate \"Waves\""}},"#,
+				r#"{"name":"thread_name","ph":"M","pid":1,"tid":1,"args":{"name":"Draw"}},"#,
+				r#"{"name":"Simulate \"Waves\"","cat":"frame","ph":"X","ts":1000.000,"dur":2000.000,"pid":1,"tid":0,"args":{"frame":0}},"#,
+				r#"{"name":"Draw","cat":"frame","ph":"X","ts":3000.000,"dur":2000.000,"pid":1,"tid":1,"args":{"frame":0}}"#,
+				r#"],"displayTimeUnit":"ms"}"#,
+			)
+		);
+	}
+
+	#[test]
+	fn only_the_last_frames_are_kept() {
+		let mut profiler = FrameProfiler::new(2);
+		for frame in 0..4 {
+			profiler.record(span(0, "Gate", frame, frame * 16, 1));
+		}
+
+		let frames: Vec<u64> = profiler.spans().map(|span| span.frame).collect();
+		assert_eq!(frames, vec![2, 3]);
+		assert_eq!(profiler.histogram("Gate").unwrap().count(), 2);
+	}
+
+	#[test]
+	fn histogram_rolls_over_its_window() {
+		let mut profiler = FrameProfiler::new(4);
+		for (frame, duration_ms) in [40, 1, 1, 3, 5].into_iter().enumerate() {
+			profiler.record(span(0, "Gate", frame as u64, 0, duration_ms));
+		}
+
+		let histogram = profiler.histogram("Gate").unwrap();
+		assert_eq!(histogram.max(), Duration::from_millis(5));
+		assert_eq!(histogram.mean(), Duration::from_micros(2500));
+		assert_eq!(histogram.percentile(0.5), Duration::from_millis(1));
+		assert_eq!(histogram.percentile(1.0), Duration::from_millis(5));
+
+		let counts: Vec<usize> = histogram.buckets().map(|(_bound, count)| count).collect();
+		assert_eq!(counts, vec![0, 0, 2, 0, 1, 1, 0, 0, 0]);
+	}
+}
@@ -0,0 +1,451 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::fixed_timestep::{FixedTimestep, TimestepState};
+use crate::render_core::viewport::Viewport;
+use crate::utils::prelude::*;
+
+/// The parts of an animation loop's frame which come from outside the app,
+/// and so have to be recorded to play the frame back the same way. There's one
+/// of these per frame, whatever the number of views, so every view replays the
+/// same times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+	pub delta_time: Duration,
+	pub raw_delta_time: Duration,
+	pub smoothed_delta_time: Duration,
+	pub frame_number: u64,
+	/// Each view's viewport width and height, in the order they were added
+	pub sizes: Vec<(f32, f32)>,
+}
+
+/// A recorded run: where each view's `FixedTimestep` was when it started, and
+/// then every frame. Replaying it from the same state gives the same
+/// simulation ticks, not just the same params.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+	pub timesteps: Vec<TimestepState>,
+	pub frames: Vec<RecordedFrame>,
+}
+
+impl RecordedFrame {
+	/// The params for the `view`th view, whose live viewport is `viewport`.
+	/// Views which weren't there when the frame was recorded keep their own
+	/// size.
+	pub fn params_for(&self, view: usize, viewport: &Viewport) -> AnimationParams {
+		let viewport = match self.sizes.get(view) {
+			Some(&(width, height)) => viewport.with_size(width, height),
+			None => viewport.clone(),
+		};
+		AnimationParams {
+			viewport,
+			delta_time: self.delta_time,
+			raw_delta_time: self.raw_delta_time,
+			smoothed_delta_time: self.smoothed_delta_time,
+			frame_number: self.frame_number,
+			fixed_ticks: None,
+			skipped_frames: 0,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingError {
+	/// Doesn't start with the recording header.
+	NotARecording,
+	UnsupportedVersion(u8),
+	/// Ends part way through a frame.
+	Truncated,
+	/// A frame number or delta time is too large to have been recorded.
+	Corrupt,
+}
+
+impl fmt::Display for RecordingError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RecordingError::NotARecording => write!(f, "Not a frame recording"),
+			RecordingError::UnsupportedVersion(version) => {
+				write!(f, "Unsupported frame recording version {version}")
+			}
+			RecordingError::Truncated => write!(f, "Frame recording is truncated"),
+			RecordingError::Corrupt => write!(f, "Frame recording is corrupt"),
+		}
+	}
+}
+
+impl std::error::Error for RecordingError {}
+
+const MAGIC: &[u8; 4] = b"WVRC";
+const VERSION: u8 = 5;
+
+/// Packs a recording into the recording format.
+///
+/// After the header comes a varint of the number of timesteps, then varints of
+/// each one's accumulated time in nanoseconds, and its next tick. Then each
+/// frame is a varint of its delta time in nanoseconds (shifted left, with the
+/// low bit set if any view's size changed), then zigzag varints of how far its
+/// raw and smoothed delta times are from that, then a varint of how many frame
+/// numbers it skips over. If the sizes changed, a varint of the number of views
+/// follows, then each one's width and height as little-endian `f32`s. A steady
+/// 60Hz frame takes seven bytes.
+///
+/// Times are kept to the nanosecond, like the live ones, since a replay
+/// rounded any coarser would run the `FixedTimestep` differently.
+pub fn encode(recording: &Recording) -> Vec<u8> {
+	let frames = &recording.frames;
+	let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + frames.len() * 7);
+	bytes.extend_from_slice(MAGIC);
+	bytes.push(VERSION);
+	write_varint(&mut bytes, recording.timesteps.len() as u64);
+	for timestep in &recording.timesteps {
+		write_varint(&mut bytes, timestep.accumulator.as_nanos() as u64);
+		write_varint(&mut bytes, timestep.next_tick);
+	}
+
+	let mut previous: Option<&RecordedFrame> = None;
+	for frame in frames {
+		let resized = previous.is_none_or(|p| p.sizes != frame.sizes);
+		let delta_nanos = frame.delta_time.as_nanos() as u64;
+		write_varint(&mut bytes, delta_nanos << 1 | resized as u64);
+		for time in [frame.raw_delta_time, frame.smoothed_delta_time] {
+			write_zigzag(&mut bytes, time.as_nanos() as i64 - delta_nanos as i64);
+		}
+		let expected_number = previous.map_or(0, |p| p.frame_number + 1);
+		write_varint(&mut bytes, frame.frame_number.wrapping_sub(expected_number));
+		if resized {
+			write_varint(&mut bytes, frame.sizes.len() as u64);
+			for (width, height) in &frame.sizes {
+				bytes.extend_from_slice(&width.to_le_bytes());
+				bytes.extend_from_slice(&height.to_le_bytes());
+			}
+		}
+		previous = Some(frame);
+	}
+	bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Recording, RecordingError> {
+	let rest = bytes.strip_prefix(MAGIC).ok_or(RecordingError::NotARecording)?;
+	let (&version, mut rest) = rest.split_first().ok_or(RecordingError::NotARecording)?;
+	if version != VERSION {
+		return Err(RecordingError::UnsupportedVersion(version));
+	}
+	let mut timesteps = Vec::new();
+	for _ in 0..read_varint(&mut rest)? {
+		timesteps.push(TimestepState {
+			accumulator: Duration::from_nanos(read_varint(&mut rest)?),
+			next_tick: read_varint(&mut rest)?,
+		});
+	}
+
+	let mut frames: Vec<RecordedFrame> = Vec::new();
+	while !rest.is_empty() {
+		let header = read_varint(&mut rest)?;
+		let resized = header & 1 == 1;
+		let delta_nanos = header >> 1;
+		let mut read_nearby_time = || -> Result<Duration, RecordingError> {
+			let offset = read_zigzag(&mut rest)?;
+			let nanos = delta_nanos.checked_add_signed(offset).ok_or(RecordingError::Corrupt)?;
+			Ok(Duration::from_nanos(nanos))
+		};
+		let raw_delta_time = read_nearby_time()?;
+		let smoothed_delta_time = read_nearby_time()?;
+		let delta_time = Duration::from_nanos(delta_nanos);
+		let expected_number = frames.last().map_or(0, |p| p.frame_number + 1);
+		let frame_number =
+			expected_number.checked_add(read_varint(&mut rest)?).ok_or(RecordingError::Corrupt)?;
+		let sizes = match (resized, frames.last()) {
+			(true, _) => {
+				let mut sizes = Vec::new();
+				for _ in 0..read_varint(&mut rest)? {
+					sizes.push((read_f32(&mut rest)?, read_f32(&mut rest)?));
+				}
+				sizes
+			}
+			(false, Some(previous)) => previous.sizes.clone(),
+			(false, None) => return Err(RecordingError::Corrupt),
+		};
+		frames.push(RecordedFrame {
+			delta_time,
+			raw_delta_time,
+			smoothed_delta_time,
+			frame_number,
+			sizes,
+		});
+	}
+	Ok(Recording { timesteps, frames })
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		bytes.push(value as u8 | 0x80);
+		value >>= 7;
+	}
+	bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, RecordingError> {
+	let mut value = 0u64;
+	for shift in (0..64).step_by(7) {
+		let (&byte, rest) = bytes.split_first().ok_or(RecordingError::Truncated)?;
+		*bytes = rest;
+		value |= ((byte & 0x7f) as u64).checked_shl(shift).ok_or(RecordingError::Corrupt)?;
+		if byte & 0x80 == 0 {
+			return Ok(value);
+		}
+	}
+	Err(RecordingError::Corrupt)
+}
+
+/// Small numbers either side of zero take a single byte.
+fn write_zigzag(bytes: &mut Vec<u8>, value: i64) {
+	write_varint(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn read_zigzag(bytes: &mut &[u8]) -> Result<i64, RecordingError> {
+	let value = read_varint(bytes)?;
+	Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn read_f32(bytes: &mut &[u8]) -> Result<f32, RecordingError> {
+	let (value, rest) = bytes.split_first_chunk::<4>().ok_or(RecordingError::Truncated)?;
+	*bytes = rest;
+	Ok(f32::from_le_bytes(*value))
+}
+
+/// Records the frames of an animation loop, or replaces them with a recording,
+/// so a run can be reproduced frame for frame.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct FrameRecorder {
+	state: Rc<RefCell<RecorderState>>,
+}
+
+#[derive(Default)]
+enum RecorderState {
+	#[default]
+	Idle,
+	/// The timesteps' states are filled in by the first frame
+	Recording(Recording),
+	Replaying {
+		/// Restored by the first frame, and then cleared
+		timesteps: Option<Vec<TimestepState>>,
+		/// The frames left to play, in reverse
+		frames: Vec<RecordedFrame>,
+	},
+}
+
+thread_local! {
+	static GLOBAL_RECORDER: FrameRecorder = FrameRecorder::default();
+}
+
+/// The recorder for the page's animation loop, which is also what JavaScript
+/// gets from `frameRecorder()`.
+#[wasm_bindgen(js_name = frameRecorder)]
+pub fn global_recorder() -> FrameRecorder { GLOBAL_RECORDER.with(FrameRecorder::clone) }
+
+impl FrameRecorder {
+	/// Records the `live` frame and returns it, or returns the replayed frame
+	/// to use instead. This should be called once per loop frame, for all of
+	/// its views. `timesteps` are the views' `FixedTimestep`s, in order, which
+	/// are saved with a recording, and restored for its replay.
+	pub fn process<'a>(
+		&self,
+		live: RecordedFrame,
+		timesteps: impl Iterator<Item = &'a RefCell<FixedTimestep>>,
+	) -> RecordedFrame {
+		let mut state = self.state.borrow_mut();
+		match &mut *state {
+			RecorderState::Idle => live,
+			RecorderState::Recording(recording) => {
+				if recording.frames.is_empty() {
+					recording.timesteps =
+						timesteps.map(|timestep| timestep.borrow().state()).collect();
+				}
+				recording.frames.push(live.clone());
+				live
+			}
+			RecorderState::Replaying { timesteps: start, frames } => {
+				if let Some(start) = start.take() {
+					// Views attached since, or gone, are left as they are
+					for (timestep, start) in timesteps.zip(start) {
+						timestep.borrow_mut().restore(start);
+					}
+				}
+				let frame = frames.pop();
+				if frames.is_empty() {
+					waves_log!("Replay finished");
+					*state = RecorderState::Idle;
+				}
+				frame.unwrap_or(live)
+			}
+		}
+	}
+
+	/// Plays back `recording` over the next frames, in place of their own.
+	pub fn replay_recording(&self, recording: Recording) {
+		let Recording { timesteps, mut frames } = recording;
+		frames.reverse();
+		self.state.replace(if frames.is_empty() {
+			RecorderState::Idle
+		} else {
+			RecorderState::Replaying { timesteps: Some(timesteps), frames }
+		});
+	}
+}
+
+#[wasm_bindgen]
+impl FrameRecorder {
+	/// Starts recording, dropping anything recorded or being replayed.
+	#[wasm_bindgen(js_name = startRecording)]
+	pub fn start_recording(&self) {
+		self.state.replace(RecorderState::Recording(Recording::default()));
+	}
+
+	/// Stops recording, and returns the recording. This is empty if nothing was
+	/// being recorded.
+	#[wasm_bindgen(js_name = stopRecording)]
+	pub fn stop_recording(&self) -> Vec<u8> {
+		match self.state.take() {
+			RecorderState::Recording(recording) => encode(&recording),
+			other => {
+				self.state.replace(other);
+				Vec::new()
+			}
+		}
+	}
+
+	/// Replays a recording made by `stopRecording`.
+	pub fn replay(&self, recording: &[u8]) -> Result<(), JsValue> {
+		let recording = decode(recording).map_err(|err| JsValue::from_str(&err.to_string()))?;
+		self.replay_recording(recording);
+		Ok(())
+	}
+
+	#[wasm_bindgen(js_name = isRecording)]
+	pub fn is_recording(&self) -> bool {
+		matches!(*self.state.borrow(), RecorderState::Recording(_))
+	}
+
+	#[wasm_bindgen(js_name = isReplaying)]
+	pub fn is_replaying(&self) -> bool {
+		matches!(*self.state.borrow(), RecorderState::Replaying { .. })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::time::Duration;
+
+	use crate::render_core::fixed_timestep::{FixedTimestep, TimestepState};
+	use crate::render_core::frame_recorder::{
+		decode, encode, FrameRecorder, RecordedFrame, Recording, RecordingError,
+	};
+
+	/// About 60Hz, which doesn't come to a whole number of microseconds
+	const FRAME_NANOS: u64 = 16_666_667;
+
+	fn frame(frame_number: u64, delta_nanos: u64, width: f32) -> RecordedFrame {
+		let delta_time = Duration::from_nanos(delta_nanos);
+		RecordedFrame {
+			delta_time,
+			raw_delta_time: delta_time,
+			smoothed_delta_time: delta_time,
+			frame_number,
+			sizes: vec![(width, 600.0)],
+		}
+	}
+
+	#[test]
+	fn recording_round_trips() {
+		let recording = Recording {
+			timesteps: vec![
+				TimestepState { accumulator: Duration::from_nanos(4_321_987), next_tick: 77 },
+				TimestepState { accumulator: Duration::ZERO, next_tick: 3 },
+			],
+			frames: vec![
+				frame(5, 0, 800.0),
+				frame(6, FRAME_NANOS, 800.0),
+				// A hitch, clamped
+				RecordedFrame {
+					raw_delta_time: Duration::from_nanos(400_000_321),
+					smoothed_delta_time: Duration::from_nanos(16_950_123),
+					..frame(9, 100_000_000, 1024.5)
+				},
+				// A second view
+				RecordedFrame { sizes: vec![(1024.5, 600.0), (320.0, 240.0)], ..frame(10, 0, 0.0) },
+				RecordedFrame { sizes: vec![(1024.5, 600.0), (320.0, 240.0)], ..frame(11, 0, 0.0) },
+			],
+		};
+		assert_eq!(decode(&encode(&recording)), Ok(recording));
+	}
+
+	#[test]
+	fn steady_frames_take_seven_bytes() {
+		let frames = (0..100).map(|number| frame(number, FRAME_NANOS, 800.0)).collect();
+		let recording = Recording { frames, ..Default::default() };
+		let header_and_first_sizes = 5 + 1 + 1 + 8;
+		assert_eq!(encode(&recording).len(), header_and_first_sizes + 100 * 7);
+	}
+
+	#[test]
+	fn bad_recordings_are_rejected() {
+		assert_eq!(decode(b"nope"), Err(RecordingError::NotARecording));
+		assert_eq!(decode(b"WVRC\x09"), Err(RecordingError::UnsupportedVersion(9)));
+
+		let recording =
+			Recording { frames: vec![frame(0, FRAME_NANOS, 800.0)], ..Default::default() };
+		let bytes = encode(&recording);
+		assert_eq!(decode(&bytes[..bytes.len() - 1]), Err(RecordingError::Truncated));
+		// A first frame has to say how big the viewports are
+		assert_eq!(decode(b"WVRC\x05\x00\x02\x00\x00\x00"), Err(RecordingError::Corrupt));
+		// Raw delta times can't be negative
+		assert_eq!(decode(b"WVRC\x05\x00\x01\x01"), Err(RecordingError::Corrupt));
+	}
+
+	#[test]
+	fn replay_restores_every_views_timestep() {
+		let recorder = FrameRecorder::default();
+		let timesteps = [
+			RefCell::new(FixedTimestep::new(Duration::from_millis(10))),
+			RefCell::new(FixedTimestep::new(Duration::from_millis(10))),
+		];
+		// Each frame goes through the recorder once, for both views
+		let run = |frames: &[RecordedFrame]| -> Vec<_> {
+			frames
+				.iter()
+				.map(|live| {
+					let frame = recorder.process(live.clone(), timesteps.iter());
+					let ticks: Vec<_> = timesteps
+						.iter()
+						.map(|timestep| timestep.borrow_mut().advance(frame.delta_time))
+						.collect();
+					(ticks, frame.sizes)
+				})
+				.collect()
+		};
+		let two_views = |number| RecordedFrame {
+			sizes: vec![(800.0, 600.0), (320.0, 240.0)],
+			..frame(number, FRAME_NANOS, 0.0)
+		};
+		let frames: Vec<_> = (0..4).map(two_views).collect();
+		run(&frames[..1]);
+		// The second view was attached later, so it's somewhere else
+		timesteps[1].borrow_mut().advance(Duration::from_millis(3));
+
+		recorder.start_recording();
+		let recorded = run(&frames[1..]);
+		let recording = decode(&recorder.stop_recording()).unwrap();
+		// Somewhere else entirely by the time it's replayed
+		run(&frames[..1]);
+
+		recorder.replay_recording(recording);
+		let replayed = run(&vec![frame(0, 5_000_000, 1024.0); 3]);
+		assert_eq!(replayed, recorded);
+		assert!(!recorder.is_replaying());
+	}
+}
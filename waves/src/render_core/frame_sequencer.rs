@@ -1,34 +1,57 @@
 use std::borrow::Borrow;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
+use single_thread_executor::timer;
+
+use crate::render_core::frame_profiler::{FrameProfiler, GateSpan};
 use crate::utils::prelude::*;
 
-pub trait FrameParams = Clone;
+pub trait FrameParams: Clone {
+	/// How far this frame moves the animation on. Gates' `min_interval`s are
+	/// measured in this time, rather than the wall clock's, so they stop
+	/// while paused, and stay deterministic while rendering offline.
+	fn elapsed(&self) -> Duration;
+
+	/// Folds in the params of an `earlier` frame, which a gate skipped because
+	/// of its `GateRate`, so the frame it does get accounts for everything
+	/// since its last one (e.g. by adding up the elapsed time).
+	fn include_skipped(&mut self, earlier: &Self);
+}
 
 /// This acts as the single-frame context. When this object is destroyed, it
 /// marks that the current task has reached the end of its frame, so it can be
-/// queued for the next one.
+/// queued for the next one, and any gates that depend on it can open.
 ///
 /// It provides automatic dereferencing to this frame's parameter values
 #[must_use]
 pub struct FrameContext<T: FrameParams> {
 	current_params: T,
 	shared_params: Rc<RefCell<Option<T>>>,
+	sequencer: Rc<FrameSequencer<T>>,
+	gate_id: usize,
+	frame: u64,
 }
 
 impl<T: FrameParams> FrameContext<T> {
-	fn new(shared_params: Rc<RefCell<Option<T>>>) -> Self {
-		let cell: &RefCell<Option<T>> = shared_params.borrow();
+	fn new(gate: &FrameGate<T>, frame: u64) -> Self {
+		let cell: &RefCell<Option<T>> = gate.params.borrow();
 		let current_value = cell.borrow().clone();
 		let current_params =
 			current_value.expect("Unable to create ParamContext with empty params");
-		Self { current_params, shared_params }
+		Self {
+			current_params,
+			shared_params: gate.params.clone(),
+			sequencer: gate.sequencer.clone(),
+			gate_id: gate.id,
+			frame,
+		}
 	}
 }
 
@@ -43,36 +66,311 @@ impl<T: FrameParams> Drop for FrameContext<T> {
 		let cell: &RefCell<Option<T>> = self.shared_params.borrow();
 		assert!(cell.borrow().is_some());
 		cell.replace(None);
+		self.sequencer.finish_frame(self.gate_id, self.frame);
+	}
+}
+
+/// What a gate does when a new frame starts before it has finished the last
+/// one, i.e. its `FrameContext` is still alive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverrunPolicy {
+	/// Drop any frames that arrive while the gate is busy, except the latest,
+	/// which it gets as soon as it's done.
+	#[default]
+	Skip,
+	/// Keep every frame, and hand them over one by one so the gate can catch
+	/// up. A gate which is always too slow will fall further and further
+	/// behind.
+	Queue,
+	/// Hold the new frame back from every gate until this one is done. If more
+	/// frames arrive in the meantime, only the latest is kept.
+	Hold,
+}
+
+/// How often a gate opens, at most. Both limits apply, so a gate opens on the
+/// first frame which satisfies both.
+///
+/// The frames in between are skipped, and folded into the next one the gate
+/// gets, through `FrameParams::include_skipped`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GateRate {
+	/// Open on every `every_nth` frame, starting with the first.
+	pub every_nth: u32,
+	/// Leave this long between openings, in `FrameParams::elapsed` time.
+	/// Frames rarely line up with it, so the gate opens on whichever frame
+	/// lands nearest each due time, keeping the average rate right.
+	pub min_interval: Duration,
+}
+
+impl GateRate {
+	pub const EVERY_FRAME: Self = Self { every_nth: 1, min_interval: Duration::ZERO };
+}
+
+impl Default for GateRate {
+	fn default() -> Self { Self::EVERY_FRAME }
+}
+
+/// Settings for a new `FrameGate`.
+pub struct GateOptions<'a, T: FrameParams> {
+	/// Gates which have to finish each frame before this one opens.
+	pub dependencies: &'a [&'a FrameGate<T>],
+	pub overrun_policy: OverrunPolicy,
+	pub rate: GateRate,
+}
+
+impl<T: FrameParams> Default for GateOptions<'_, T> {
+	fn default() -> Self {
+		Self {
+			dependencies: &[],
+			overrun_policy: OverrunPolicy::default(),
+			rate: GateRate::default(),
+		}
+	}
+}
+
+struct GateEntry<T: FrameParams> {
+	name: String,
+	params: Rc<RefCell<Option<T>>>,
+	waker: Option<Waker>,
+	/// Gates which have to finish each frame before this one opens
+	dependencies: Vec<usize>,
+	policy: OverrunPolicy,
+	/// Frames which have been handed to this gate, but not opened yet
+	pending: VecDeque<(u64, T)>,
+	/// The frame this gate has been opened for, until its `FrameContext` drops
+	open_frame: Option<u64>,
+	opened_at: Duration,
+	finished_frame: Option<u64>,
+	overruns: u64,
+	rate: GateRate,
+	/// The frames skipped since the last one delivered, folded together
+	skipped: Option<T>,
+	frames_until_due: u32,
+	last_skipped_frame: Option<u64>,
+	next_due: Duration,
+}
+
+impl<T: FrameParams> GateEntry<T> {
+	fn is_busy(&self) -> bool { self.open_frame.is_some() }
+
+	fn is_due(&self, now: Duration, half_frame: Duration) -> bool {
+		self.frames_until_due == 0 && now + half_frame >= self.next_due
+	}
+
+	fn skip(&mut self, frame: u64, mut params: T) {
+		if let Some(earlier) = self.skipped.take() {
+			params.include_skipped(&earlier);
+		}
+		self.skipped = Some(params);
+		self.frames_until_due = self.frames_until_due.saturating_sub(1);
+		self.last_skipped_frame = Some(frame);
+		// There's nothing to do for this frame, so it's done as far as any
+		// dependent gates are concerned
+		if !self.is_busy() && self.pending.is_empty() {
+			self.finished_frame = Some(frame);
+		}
+	}
+
+	/// The params to deliver for this frame, including any skipped before it.
+	fn take_due(&mut self, now: Duration, mut params: T) -> T {
+		if let Some(earlier) = self.skipped.take() {
+			params.include_skipped(&earlier);
+		}
+		self.frames_until_due = self.rate.every_nth - 1;
+		// Keeps to the schedule, unless it's fallen a whole interval behind
+		self.next_due = if self.next_due + self.rate.min_interval > now {
+			self.next_due + self.rate.min_interval
+		} else {
+			now + self.rate.min_interval
+		};
+		params
 	}
 }
 
 pub struct FrameSequencer<T: FrameParams> {
-	running_gates: RefCell<HashMap<usize, (Rc<RefCell<Option<T>>>, Option<Waker>)>>,
+	running_gates: RefCell<HashMap<usize, GateEntry<T>>>,
 	next_id: Cell<usize>,
+	next_frame: Cell<u64>,
+	/// A frame kept back until every `OverrunPolicy::Hold` gate is done
+	held_frame: RefCell<Option<(u64, T)>>,
+	/// The total `FrameParams::elapsed` of every frame so far
+	elapsed: Cell<Duration>,
+	profiler: Rc<RefCell<FrameProfiler>>,
 }
 
 impl<T: FrameParams> FrameSequencer<T> {
 	pub fn new() -> Self {
-		Self { running_gates: RefCell::new(HashMap::default()), next_id: Cell::new(0) }
+		Self {
+			running_gates: RefCell::new(HashMap::default()),
+			next_id: Cell::new(0),
+			next_frame: Cell::new(0),
+			held_frame: RefCell::new(None),
+			elapsed: Cell::new(Duration::ZERO),
+			profiler: Rc::default(),
+		}
 	}
 
-	fn register(self: &Rc<FrameSequencer<T>>) -> (usize, Rc<RefCell<Option<T>>>) {
+	/// Times every gate's frames, from opening until their `FrameContext`
+	/// drops.
+	pub fn profiler(&self) -> Rc<RefCell<FrameProfiler>> { self.profiler.clone() }
+
+	fn register(
+		self: &Rc<FrameSequencer<T>>,
+		name: String,
+		dependencies: Vec<usize>,
+		policy: OverrunPolicy,
+		rate: GateRate,
+	) -> (usize, Rc<RefCell<Option<T>>>) {
 		let next_id = self.next_id.get();
 		self.next_id.replace(next_id + 1);
 
-		self.running_gates.borrow_mut().insert(next_id, (Rc::new(RefCell::new(None)), None));
-		(next_id, self.running_gates.borrow().get(&next_id).unwrap().0.clone())
+		let params = Rc::new(RefCell::new(None));
+		self.running_gates.borrow_mut().insert(
+			next_id,
+			GateEntry {
+				name,
+				params: params.clone(),
+				waker: None,
+				dependencies,
+				policy,
+				pending: VecDeque::new(),
+				open_frame: None,
+				opened_at: Duration::ZERO,
+				finished_frame: None,
+				overruns: 0,
+				rate,
+				skipped: None,
+				frames_until_due: 0,
+				last_skipped_frame: None,
+				next_due: Duration::ZERO,
+			},
+		);
+		(next_id, params)
 	}
 
 	fn mark_all_running(self: &Rc<FrameSequencer<T>>, params: T) {
-		for (_id, (running, maybe_waker)) in self.running_gates.borrow().iter() {
-			let running_params: &RefCell<Option<T>> = running.borrow();
-			running_params.replace(Some(params.clone()));
+		let frame = self.next_frame.get();
+		self.next_frame.set(frame + 1);
+		self.elapsed.set(self.elapsed.get() + params.elapsed());
 
-			if maybe_waker.is_some() {
-				maybe_waker.as_ref().unwrap().clone().wake();
+		let mut holding = false;
+		for gate in self.running_gates.borrow_mut().values_mut() {
+			if gate.policy == OverrunPolicy::Hold && gate.is_busy() {
+				gate.overruns += 1;
+				holding = true;
 			}
 		}
+		if holding {
+			self.held_frame.replace(Some((frame, params)));
+			return;
+		}
+		self.deliver(frame, params);
+	}
+
+	/// Hands a frame to every gate which is due one, according to their
+	/// `OverrunPolicy`.
+	fn deliver(self: &Rc<FrameSequencer<T>>, frame: u64, params: T) {
+		let now = self.elapsed.get();
+		let half_frame = params.elapsed() / 2;
+		for gate in self.running_gates.borrow_mut().values_mut() {
+			if !gate.is_due(now, half_frame) {
+				gate.skip(frame, params.clone());
+				continue;
+			}
+			let params = gate.take_due(now, params.clone());
+			if gate.is_busy() {
+				gate.overruns += 1;
+			}
+			if gate.policy == OverrunPolicy::Skip {
+				gate.pending.clear();
+			}
+			gate.pending.push_back((frame, params));
+		}
+		self.open_ready_gates();
+	}
+
+	/// Opens every idle gate with a pending frame, whose dependencies have all
+	/// finished that frame (or a later one).
+	fn open_ready_gates(self: &Rc<FrameSequencer<T>>) {
+		let mut wakers = Vec::new();
+		let now = timer::now();
+		{
+			let mut gates = self.running_gates.borrow_mut();
+			let ready: Vec<usize> = gates
+				.iter()
+				.filter(|(_id, gate)| {
+					let Some((frame, _params)) = gate.pending.front().filter(|_| !gate.is_busy())
+					else {
+						return false;
+					};
+					gate.dependencies.iter().all(|dependency| {
+						// A dependency that's gone can't hold anything up
+						gates.get(dependency).is_none_or(|d| d.finished_frame >= Some(*frame))
+					})
+				})
+				.map(|(id, _gate)| *id)
+				.collect();
+
+			for id in ready {
+				let gate = gates.get_mut(&id).unwrap();
+				let (frame, params) = gate.pending.pop_front().unwrap();
+				gate.open_frame = Some(frame);
+				gate.opened_at = now;
+				let running_params: &RefCell<Option<T>> = gate.params.borrow();
+				running_params.replace(Some(params));
+				wakers.extend(gate.waker.clone());
+			}
+		}
+		wakers.into_iter().for_each(Waker::wake);
+	}
+
+	fn finish_frame(self: &Rc<FrameSequencer<T>>, gate_id: usize, frame: u64) {
+		if let Some(gate) = self.running_gates.borrow_mut().get_mut(&gate_id) {
+			gate.open_frame = None;
+			// Any frames skipped while it was busy are done with too
+			let skipped_frame = gate.last_skipped_frame.filter(|_| gate.pending.is_empty());
+			gate.finished_frame = Some(frame).max(skipped_frame);
+			self.profiler.borrow_mut().record(GateSpan {
+				gate_id,
+				gate: gate.name.clone(),
+				frame,
+				start: gate.opened_at,
+				duration: timer::now().saturating_sub(gate.opened_at),
+			});
+		}
+		self.release_held_frame();
+		self.open_ready_gates();
+	}
+
+	fn release_held_frame(self: &Rc<FrameSequencer<T>>) {
+		let holding = self
+			.running_gates
+			.borrow()
+			.values()
+			.any(|gate| gate.policy == OverrunPolicy::Hold && gate.is_busy());
+		if holding {
+			return;
+		}
+		let held_frame = self.held_frame.borrow_mut().take();
+		if let Some((frame, params)) = held_frame {
+			self.deliver(frame, params);
+		}
+	}
+
+	fn open_frame(self: &Rc<FrameSequencer<T>>, gate_id: usize) -> u64 {
+		let gates = self.running_gates.borrow();
+		let gate =
+			gates.get(&gate_id).unwrap_or_else(|| panic!("Could not find gate id {gate_id}"));
+		gate.open_frame.expect("Gate has not been opened")
+	}
+
+	fn with_gate<R>(
+		self: &Rc<FrameSequencer<T>>,
+		gate_id: usize,
+		f: impl FnOnce(&mut GateEntry<T>) -> R,
+	) -> R {
+		let mut gates = self.running_gates.borrow_mut();
+		f(gates.get_mut(&gate_id).unwrap_or_else(|| panic!("Could not find gate id {gate_id}")))
 	}
 
 	fn update_waker(self: &Rc<FrameSequencer<T>>, gate_id: usize, waker: Waker) {
@@ -80,13 +378,18 @@ impl<T: FrameParams> FrameSequencer<T> {
 		let entry =
 			gates.get_mut(&gate_id).expect(format!("Could not find gate id {gate_id}").as_str());
 
-		(*entry).1 = Some(waker);
+		entry.waker = Some(waker);
 	}
 
 	fn remove_gate(self: &Rc<FrameSequencer<T>>, gate_id: usize) {
 		let mut gates = self.running_gates.borrow_mut();
 		gates.remove(&gate_id).expect(format!("Unable to find gate ID {gate_id}").as_str());
+		drop(gates);
 		waves_log!("Removed gate ID {gate_id}");
+
+		// Anything waiting on this gate no longer has to
+		self.release_held_frame();
+		self.open_ready_gates();
 	}
 }
 
@@ -100,9 +403,54 @@ pub struct FrameGate<T: FrameParams> {
 
 impl<T: FrameParams> FrameGate<T> {
 	pub fn new(sequencer: Rc<FrameSequencer<T>>, name: String) -> Self {
-		let (id, params) = sequencer.register();
+		Self::with_dependencies(sequencer, name, &[])
+	}
+
+	/// Creates a gate which only opens each frame once every gate in
+	/// `dependencies` has finished that frame, i.e. dropped its `FrameContext`.
+	/// Dependencies have to exist before the gates that depend on them, so
+	/// they can never form a cycle.
+	pub fn with_dependencies(
+		sequencer: Rc<FrameSequencer<T>>,
+		name: String,
+		dependencies: &[&FrameGate<T>],
+	) -> Self {
+		Self::with_options(sequencer, name, GateOptions { dependencies, ..Default::default() })
+	}
+
+	pub fn with_options(
+		sequencer: Rc<FrameSequencer<T>>,
+		name: String,
+		options: GateOptions<'_, T>,
+	) -> Self {
+		assert!(options.rate.every_nth > 0, "Gate {name} can't open every 0th frame");
+		for dependency in options.dependencies {
+			assert!(
+				Rc::ptr_eq(&dependency.sequencer, &sequencer),
+				"Gate {name} can't depend on {}, which belongs to another sequencer",
+				dependency.name
+			);
+		}
+		let dependencies = options.dependencies.iter().map(|dependency| dependency.id).collect();
+		let (id, params) =
+			sequencer.register(name.clone(), dependencies, options.overrun_policy, options.rate);
 		Self { sequencer, id, params, name, frame_waker: Cell::new(None) }
 	}
+
+	pub fn overrun_policy(&self) -> OverrunPolicy {
+		self.sequencer.with_gate(self.id, |gate| gate.policy)
+	}
+
+	pub fn set_overrun_policy(&self, policy: OverrunPolicy) {
+		self.sequencer.with_gate(self.id, |gate| gate.policy = policy);
+		// Switching away from `Hold` may free up a held frame
+		self.sequencer.release_held_frame();
+		self.sequencer.open_ready_gates();
+	}
+
+	/// The number of frames which started while this gate was still busy with
+	/// an earlier one.
+	pub fn overruns(&self) -> u64 { self.sequencer.with_gate(self.id, |gate| gate.overruns) }
 }
 
 impl<T: FrameParams> Future for &FrameGate<T> {
@@ -114,9 +462,8 @@ impl<T: FrameParams> Future for &FrameGate<T> {
 
 		let running_params: &RefCell<Option<T>> = self.params.borrow();
 		if running_params.borrow().is_some() {
-			// let params = running_params.replace(None);
-			// assert!(params.is_some());
-			Poll::Ready(FrameContext::new(self.params.clone()))
+			let frame = self.sequencer.open_frame(self.id);
+			Poll::Ready(FrameContext::new(*self, frame))
 		} else {
 			Poll::Pending
 		}
@@ -139,3 +486,355 @@ impl<T: FrameParams> FrameMarker<T> {
 
 	pub fn frame(&self, params: T) { self.sequencer.mark_all_running(params) }
 }
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+	use std::time::Duration;
+
+	use single_thread_executor::sync::Semaphore;
+	use single_thread_executor::timer::{self, ManualClock};
+	use single_thread_executor::{new_executor_and_spawner, JoinError, ShutdownMode, Spawner};
+
+	use crate::render_core::frame_sequencer::{
+		FrameGate, FrameMarker, FrameParams, FrameSequencer, GateOptions, GateRate, OverrunPolicy,
+	};
+
+	/// Tests treat skipped frames' params as elapsed time, so they add up
+	impl FrameParams for u64 {
+		fn elapsed(&self) -> Duration { Duration::from_millis(*self) }
+
+		fn include_skipped(&mut self, earlier: &Self) { *self += earlier; }
+	}
+
+	#[test]
+	fn gate_opens_once_per_frame() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let gate = FrameGate::new(sequencer.clone(), "Test Gate".to_owned());
+
+		let seen = Rc::new(RefCell::new(Vec::new()));
+		let task_seen = seen.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&gate).await;
+				task_seen.borrow_mut().push(*params);
+			}
+		});
+
+		executor.run_until_stalled();
+		assert!(seen.borrow().is_empty());
+
+		marker.frame(0);
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0]);
+
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0]);
+
+		marker.frame(1);
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0, 1]);
+	}
+
+	#[test]
+	fn gate_unregisters_when_task_finishes() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let gate = FrameGate::new(sequencer.clone(), "One Frame".to_owned());
+
+		let handle = spawner.spawn_with_handle(async move { *(&gate).await });
+		executor.run_until_stalled();
+		assert_eq!(sequencer.running_gates.borrow().len(), 1);
+
+		marker.frame(7);
+		assert_eq!(executor.block_on(handle), Ok(7));
+		assert!(sequencer.running_gates.borrow().is_empty());
+	}
+
+	#[test]
+	fn gate_unregisters_when_task_is_aborted() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let gate = FrameGate::new(sequencer.clone(), "Aborted".to_owned());
+
+		let handle = spawner.spawn_with_handle(async move { *(&gate).await });
+		executor.run_until_stalled();
+		assert_eq!(sequencer.running_gates.borrow().len(), 1);
+
+		handle.abort();
+		assert!(sequencer.running_gates.borrow().is_empty());
+		assert_eq!(executor.block_on(handle), Err(JoinError::Cancelled));
+	}
+
+	#[test]
+	fn shutdown_frees_waiting_gate_tasks() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let gate = FrameGate::new(sequencer.clone(), "Waiting".to_owned());
+		spawner.spawn(async move {
+			loop {
+				drop((&gate).await);
+			}
+		});
+		executor.run_until_stalled();
+
+		// The sequencer keeps the task's waker, and the task keeps the gate
+		spawner.shutdown(ShutdownMode::Cancel);
+		assert!(sequencer.running_gates.borrow().is_empty());
+		assert_eq!(Rc::strong_count(&sequencer), 1);
+	}
+
+	#[test]
+	fn dependent_gate_waits_for_its_dependency() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let first = FrameGate::new(sequencer.clone(), "First".to_owned());
+		let second =
+			FrameGate::with_dependencies(sequencer.clone(), "Second".to_owned(), &[&first]);
+
+		let order = Rc::new(RefCell::new(Vec::new()));
+		// Spawned first, so it would run first if nothing held it back
+		let second_order = order.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&second).await;
+				second_order.borrow_mut().push(("second", *params));
+			}
+		});
+		let resume = Rc::new(Semaphore::new(0));
+		let resumed = resume.clone();
+		let first_order = order.clone();
+		spawner.spawn(async move {
+			loop {
+				let params = (&first).await;
+				first_order.borrow_mut().push(("first", *params));
+				// Holding the context across an await keeps the second gate shut
+				resumed.acquire().await.forget();
+			}
+		});
+
+		marker.frame(0);
+		executor.run_until_stalled();
+		assert_eq!(*order.borrow(), vec![("first", 0)]);
+
+		resume.add_permits(1);
+		executor.run_until_stalled();
+		assert_eq!(*order.borrow(), vec![("first", 0), ("second", 0)]);
+
+		marker.frame(1);
+		resume.add_permits(1);
+		executor.run_until_stalled();
+		assert_eq!(*order.borrow(), vec![("first", 0), ("second", 0), ("first", 1), ("second", 1)]);
+	}
+
+	#[test]
+	fn dropped_dependency_stops_blocking() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let first = FrameGate::new(sequencer.clone(), "First".to_owned());
+		let second =
+			FrameGate::with_dependencies(sequencer.clone(), "Second".to_owned(), &[&first]);
+
+		let handle = spawner.spawn_with_handle(async move { *(&second).await });
+		marker.frame(3);
+		executor.run_until_stalled();
+		assert!(!handle.is_finished());
+
+		drop(first);
+		assert_eq!(executor.block_on(handle), Ok(3));
+	}
+
+	/// Spawns a task which records each frame it sees, and then holds on to
+	/// that frame's context until given a permit.
+	fn slow_gate_task(
+		spawner: &Spawner,
+		gate: FrameGate<u64>,
+	) -> (Rc<RefCell<Vec<u64>>>, Rc<Semaphore>) {
+		let seen = Rc::new(RefCell::new(Vec::new()));
+		let finish = Rc::new(Semaphore::new(0));
+		let (task_seen, task_finish) = (seen.clone(), finish.clone());
+		spawner.spawn(async move {
+			loop {
+				let params = (&gate).await;
+				task_seen.borrow_mut().push(*params);
+				task_finish.acquire().await.forget();
+			}
+		});
+		(seen, finish)
+	}
+
+	#[test]
+	fn skip_policy_catches_up_to_the_latest_frame() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let gate = FrameGate::new(sequencer.clone(), "Slow".to_owned());
+		let stats = FrameGate::new(sequencer.clone(), "Stats".to_owned());
+		let (seen, finish) = slow_gate_task(&spawner, gate);
+
+		for frame in 0..3 {
+			marker.frame(frame);
+			executor.run_until_stalled();
+		}
+		assert_eq!(*seen.borrow(), vec![0]);
+
+		finish.add_permits(1);
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0, 2]);
+		// Nobody awaits this gate, so it overran every frame after the first
+		assert_eq!(stats.overruns(), 2);
+	}
+
+	#[test]
+	fn queue_policy_delivers_every_frame() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let gate = FrameGate::new(sequencer.clone(), "Slow".to_owned());
+		gate.set_overrun_policy(OverrunPolicy::Queue);
+		let (seen, finish) = slow_gate_task(&spawner, gate);
+
+		for frame in 0..3 {
+			marker.frame(frame);
+			executor.run_until_stalled();
+		}
+		finish.add_permits(2);
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0, 1, 2]);
+	}
+
+	#[test]
+	fn hold_policy_holds_back_every_gate() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let slow = FrameGate::new(sequencer.clone(), "Slow".to_owned());
+		slow.set_overrun_policy(OverrunPolicy::Hold);
+		let fast = FrameGate::new(sequencer.clone(), "Fast".to_owned());
+		let (slow_seen, finish) = slow_gate_task(&spawner, slow);
+		let (fast_seen, fast_finish) = slow_gate_task(&spawner, fast);
+		fast_finish.add_permits(100);
+
+		for frame in 0..3 {
+			marker.frame(frame);
+			executor.run_until_stalled();
+		}
+		assert_eq!(*slow_seen.borrow(), vec![0]);
+		assert_eq!(*fast_seen.borrow(), vec![0]);
+
+		finish.add_permits(1);
+		executor.run_until_stalled();
+		assert_eq!(*slow_seen.borrow(), vec![0, 2]);
+		assert_eq!(*fast_seen.borrow(), vec![0, 2]);
+	}
+
+	#[test]
+	fn profiler_times_gates_until_their_context_drops() {
+		let clock = Rc::new(ManualClock::new());
+		timer::set_clock(clock.clone());
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let gate = FrameGate::new(sequencer.clone(), "Slow".to_owned());
+		let (_seen, finish) = slow_gate_task(&spawner, gate);
+
+		clock.advance(Duration::from_millis(10));
+		marker.frame(0);
+		executor.run_until_stalled();
+		clock.advance(Duration::from_millis(3));
+		finish.add_permits(1);
+		executor.run_until_stalled();
+
+		let profiler = sequencer.profiler();
+		let profiler = profiler.borrow();
+		let spans: Vec<_> = profiler.spans().collect();
+		assert_eq!(spans.len(), 1);
+		assert_eq!(spans[0].gate, "Slow");
+		assert_eq!(spans[0].frame, 0);
+		assert_eq!(spans[0].start, Duration::from_millis(10));
+		assert_eq!(spans[0].duration, Duration::from_millis(3));
+	}
+
+	#[test]
+	fn every_nth_gate_gets_the_skipped_frames_folded_in() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let rate = GateRate { every_nth: 3, ..Default::default() };
+		let every_third = FrameGate::with_options(
+			sequencer.clone(),
+			"Every Third".to_owned(),
+			GateOptions { rate, ..Default::default() },
+		);
+		let after =
+			FrameGate::with_dependencies(sequencer.clone(), "After".to_owned(), &[&every_third]);
+		let (seen, finish) = slow_gate_task(&spawner, every_third);
+		finish.add_permits(100);
+		let (after_seen, after_finish) = slow_gate_task(&spawner, after);
+		after_finish.add_permits(100);
+
+		for _ in 0..7 {
+			marker.frame(1);
+			executor.run_until_stalled();
+		}
+		assert_eq!(*seen.borrow(), vec![1, 3, 3]);
+		// Skipped frames don't hold up the gates depending on it
+		assert_eq!(*after_seen.borrow(), vec![1; 7]);
+	}
+
+	#[test]
+	fn min_interval_gate_keeps_to_its_rate_on_average() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let rate = GateRate { min_interval: Duration::from_millis(100), ..Default::default() };
+		let gate = FrameGate::with_options(
+			sequencer.clone(),
+			"Ten Hz".to_owned(),
+			GateOptions { rate, ..Default::default() },
+		);
+		let (seen, finish) = slow_gate_task(&spawner, gate);
+		finish.add_permits(100);
+
+		// 100ms isn't a whole number of 30ms frames, so the gate alternates
+		// between opening every third and every fourth frame. Like the
+		// animation loop's, the first frame takes no time.
+		marker.frame(0);
+		executor.run_until_stalled();
+		for _ in 0..10 {
+			marker.frame(30);
+			executor.run_until_stalled();
+		}
+		assert_eq!(*seen.borrow(), vec![0, 90, 120, 90]);
+	}
+
+	#[test]
+	fn min_interval_gate_waits_while_no_time_passes() {
+		let (executor, spawner) = new_executor_and_spawner();
+		let sequencer = Rc::new(FrameSequencer::<u64>::new());
+		let marker = FrameMarker::new(sequencer.clone());
+		let rate = GateRate { min_interval: Duration::from_millis(100), ..Default::default() };
+		let gate = FrameGate::with_options(
+			sequencer.clone(),
+			"Ten Hz".to_owned(),
+			GateOptions { rate, ..Default::default() },
+		);
+		let (seen, finish) = slow_gate_task(&spawner, gate);
+		finish.add_permits(100);
+
+		// e.g. while paused, however much wall clock time goes by
+		for _ in 0..5 {
+			marker.frame(0);
+			executor.run_until_stalled();
+		}
+		marker.frame(100);
+		executor.run_until_stalled();
+		assert_eq!(*seen.borrow(), vec![0, 100]);
+	}
+}
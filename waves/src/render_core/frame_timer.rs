@@ -0,0 +1,161 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use web_sys::Document;
+
+use crate::utils::prelude::*;
+
+/// A frame's timings, before and after `FrameTimer` has cleaned them up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTimes {
+	/// The time since the last frame, as measured
+	pub raw: Duration,
+	/// The time the animation should advance by
+	pub delta: Duration,
+	/// A moving average of `delta`, for steadier readouts
+	pub smoothed: Duration,
+}
+
+/// Keeps long gaps between frames from throwing the animation forwards.
+///
+/// A hitch (e.g. a garbage collection) has its delta clamped, and the gap
+/// after `reset` (e.g. while the page was hidden, and the browser stopped
+/// sending frames) counts as a single ordinary frame.
+pub struct FrameTimer {
+	max_delta: Duration,
+	smoothed: Option<Duration>,
+	resuming: bool,
+}
+
+impl FrameTimer {
+	/// Long enough that only real hitches are cut short, even at 15Hz.
+	pub const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(100);
+	/// How much each new frame moves the smoothed time
+	const SMOOTHING: f64 = 0.1;
+
+	pub fn new(max_delta: Duration) -> Self { Self { max_delta, smoothed: None, resuming: false } }
+
+	/// Makes the gap before the next frame count as an ordinary frame.
+	pub fn reset(&mut self) { self.resuming = true; }
+
+	pub fn frame(&mut self, raw: Duration) -> FrameTimes {
+		let delta = if std::mem::take(&mut self.resuming) {
+			self.smoothed.unwrap_or_default().min(raw)
+		} else {
+			raw.min(self.max_delta)
+		};
+		self.times(raw, delta)
+	}
+
+	/// Takes a frame's time as it is, e.g. when frames are asked for a fixed
+	/// time apart, rather than measured. It still counts towards the smoothed
+	/// time.
+	pub fn exact_frame(&mut self, raw: Duration) -> FrameTimes { self.times(raw, raw) }
+
+	fn times(&mut self, raw: Duration, delta: Duration) -> FrameTimes {
+		// Frames without any time passing (e.g. the first) would drag the
+		// average down
+		if !delta.is_zero() {
+			self.smoothed = Some(match self.smoothed {
+				Some(smoothed) => {
+					smoothed.mul_f64(1.0 - Self::SMOOTHING) + delta.mul_f64(Self::SMOOTHING)
+				}
+				None => delta,
+			});
+		}
+		FrameTimes { raw, delta, smoothed: self.smoothed.unwrap_or_default() }
+	}
+}
+
+impl Default for FrameTimer {
+	fn default() -> Self { Self::new(Self::DEFAULT_MAX_DELTA) }
+}
+
+/// Notices the page being shown again after it was hidden, until dropped.
+pub struct VisibilityListener {
+	document: Document,
+	closure: Closure<dyn FnMut()>,
+	shown: Rc<Cell<bool>>,
+}
+
+impl VisibilityListener {
+	pub fn new() -> Self {
+		let document = web_sys::window().unwrap().document().unwrap();
+		let shown = Rc::new(Cell::new(false));
+		let (listener_document, listener_shown) = (document.clone(), shown.clone());
+		let closure = Closure::new(move || {
+			if !listener_document.hidden() {
+				listener_shown.set(true);
+			}
+		});
+		document
+			.add_event_listener_with_callback("visibilitychange", closure.as_ref().unchecked_ref())
+			.expect("should listen for visibility changes OK");
+		Self { document, closure, shown }
+	}
+
+	/// Whether the page has been shown again since this was last asked.
+	pub fn take_shown(&self) -> bool { self.shown.take() }
+}
+
+impl Drop for VisibilityListener {
+	fn drop(&mut self) {
+		let _ = self.document.remove_event_listener_with_callback(
+			"visibilitychange",
+			self.closure.as_ref().unchecked_ref(),
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use crate::render_core::frame_timer::FrameTimer;
+
+	fn millis(millis: u64) -> Duration { Duration::from_millis(millis) }
+
+	#[test]
+	fn hitches_are_clamped() {
+		let mut timer = FrameTimer::new(millis(100));
+		let times = timer.frame(millis(400));
+		assert_eq!(times.raw, millis(400));
+		assert_eq!(times.delta, millis(100));
+		assert_eq!(timer.frame(millis(16)).delta, millis(16));
+	}
+
+	#[test]
+	fn exact_frames_are_never_clamped() {
+		let mut timer = FrameTimer::new(millis(100));
+		timer.reset();
+		let times = timer.exact_frame(millis(400));
+		assert_eq!(times.delta, millis(400));
+		assert_eq!(times.smoothed, millis(400));
+	}
+
+	#[test]
+	fn reset_turns_the_gap_into_an_ordinary_frame() {
+		let mut timer = FrameTimer::default();
+		for _ in 0..10 {
+			timer.frame(millis(16));
+		}
+
+		timer.reset();
+		let times = timer.frame(Duration::from_secs(30));
+		assert_eq!(times.raw, Duration::from_secs(30));
+		assert_eq!(times.delta, millis(16));
+		// Only the one frame after the reset
+		assert_eq!(timer.frame(millis(50)).delta, millis(50));
+	}
+
+	#[test]
+	fn smoothed_time_follows_the_frame_rate() {
+		let mut timer = FrameTimer::default();
+		assert_eq!(timer.frame(Duration::ZERO).smoothed, Duration::ZERO);
+		assert_eq!(timer.frame(millis(16)).smoothed, millis(16));
+
+		let smoothed = (0..100).map(|_| timer.frame(millis(33)).smoothed).last().unwrap();
+		assert!((smoothed.as_secs_f64() - 0.033).abs() < 1e-4, "{smoothed:?}");
+	}
+}
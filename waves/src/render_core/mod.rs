@@ -1,8 +1,15 @@
 pub mod animation;
+pub mod animation_controller;
 pub mod animation_params;
 pub mod camera;
 pub mod canvas;
+pub mod fixed_timestep;
+pub mod frame_capture;
+pub mod frame_clock;
+pub mod frame_profiler;
+pub mod frame_recorder;
 pub mod frame_sequencer;
+pub mod frame_timer;
 pub mod image;
 pub mod mesh;
 pub mod ping_pong_buffer;
@@ -10,3 +17,4 @@ pub mod shader;
 pub mod texture;
 pub mod uniform;
 pub mod viewport;
+pub mod zip_archive;
@@ -44,6 +44,12 @@ impl Viewport {
 
 	pub fn context(&self) -> &WebGl2RenderingContext { &self.context }
 
+	/// A copy of this viewport which reports the given size instead of the
+	/// canvas's, e.g. to replay frames recorded at another size.
+	pub fn with_size(&self, width: f32, height: f32) -> Self {
+		Self { width: RefCell::new(width), height: RefCell::new(height), ..self.clone() }
+	}
+
 	pub fn width(&self) -> f32 { self.width.borrow().clone() }
 
 	pub fn height(&self) -> f32 { self.height.borrow().clone() }
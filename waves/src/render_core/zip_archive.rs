@@ -0,0 +1,144 @@
+/// Builds a zip file in memory, storing files as they are. This suits files
+/// which are already compressed, like PNGs.
+pub struct ZipArchive {
+	bytes: Vec<u8>,
+	central_directory: Vec<u8>,
+	entries: u16,
+}
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+/// Version 2.0, the first with folders, which every unzipper understands
+const VERSION: u16 = 20;
+/// Stored, i.e. not compressed
+const METHOD: u16 = 0;
+/// The 1st of January 1980, the earliest time zip can store
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+impl ZipArchive {
+	pub fn new() -> Self { Self { bytes: Vec::new(), central_directory: Vec::new(), entries: 0 } }
+
+	/// Adds a file. Zip without its 64-bit extensions can't hold more than
+	/// 65535 files, or anything past 4GB.
+	pub fn add_file(&mut self, name: &str, contents: &[u8]) {
+		let offset = u32::try_from(self.bytes.len()).expect("Zip archive is too large");
+		let size = u32::try_from(contents.len()).expect("File is too large to zip");
+		let name_length = u16::try_from(name.len()).expect("File name is too long to zip");
+		self.entries = self.entries.checked_add(1).expect("Too many files to zip");
+		let crc = crc32(contents);
+
+		let bytes = &mut self.bytes;
+		put_u32(bytes, LOCAL_HEADER_SIGNATURE);
+		for value in [VERSION, 0, METHOD, 0, DOS_DATE] {
+			put_u16(bytes, value);
+		}
+		for value in [crc, size, size] {
+			put_u32(bytes, value);
+		}
+		put_u16(bytes, name_length);
+		put_u16(bytes, 0);
+		bytes.extend_from_slice(name.as_bytes());
+		bytes.extend_from_slice(contents);
+
+		let directory = &mut self.central_directory;
+		put_u32(directory, CENTRAL_HEADER_SIGNATURE);
+		for value in [VERSION, VERSION, 0, METHOD, 0, DOS_DATE] {
+			put_u16(directory, value);
+		}
+		for value in [crc, size, size] {
+			put_u32(directory, value);
+		}
+		// Name, extra field and comment lengths, disk number and attributes
+		for value in [name_length, 0, 0, 0, 0] {
+			put_u16(directory, value);
+		}
+		put_u32(directory, 0);
+		put_u32(directory, offset);
+		directory.extend_from_slice(name.as_bytes());
+	}
+
+	pub fn finish(mut self) -> Vec<u8> {
+		let directory_offset = u32::try_from(self.bytes.len()).expect("Zip archive is too large");
+		let directory_size = self.central_directory.len() as u32;
+		self.bytes.append(&mut self.central_directory);
+
+		let bytes = &mut self.bytes;
+		put_u32(bytes, END_OF_DIRECTORY_SIGNATURE);
+		for value in [0, 0, self.entries, self.entries] {
+			put_u16(bytes, value);
+		}
+		put_u32(bytes, directory_size);
+		put_u32(bytes, directory_offset);
+		put_u16(bytes, 0);
+		self.bytes
+	}
+}
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) { bytes.extend_from_slice(&value.to_le_bytes()); }
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) { bytes.extend_from_slice(&value.to_le_bytes()); }
+
+fn crc32(bytes: &[u8]) -> u32 {
+	const TABLE: [u32; 256] = {
+		let mut table = [0u32; 256];
+		let mut i = 0;
+		while i < 256 {
+			let mut crc = i as u32;
+			let mut bit = 0;
+			while bit < 8 {
+				crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+				bit += 1;
+			}
+			table[i] = crc;
+			i += 1;
+		}
+		table
+	};
+
+	!bytes
+		.iter()
+		.fold(!0u32, |crc, &byte| TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::render_core::zip_archive::{crc32, ZipArchive};
+
+	fn u16_at(bytes: &[u8], at: usize) -> u16 { u16::from_le_bytes([bytes[at], bytes[at + 1]]) }
+
+	fn u32_at(bytes: &[u8], at: usize) -> u32 {
+		u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+	}
+
+	#[test]
+	fn crc_matches_the_standard_check_value() {
+		assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+		assert_eq!(crc32(b""), 0);
+	}
+
+	#[test]
+	fn archive_lists_every_file() {
+		let mut archive = ZipArchive::new();
+		archive.add_file("a.txt", b"hello");
+		archive.add_file("b.txt", b"world!");
+		let bytes = archive.finish();
+
+		let local_size = 30 + 5 + 5 + 30 + 5 + 6;
+		let directory_size = 2 * 46 + 5 + 5;
+		assert_eq!(bytes.len(), local_size + directory_size + 22);
+		assert_eq!(&bytes[30..35], b"a.txt");
+		assert_eq!(&bytes[35..40], b"hello");
+		assert_eq!(u32_at(&bytes, 14), crc32(b"hello"));
+
+		let end = bytes.len() - 22;
+		assert_eq!(u32_at(&bytes, end), 0x0605_4b50);
+		assert_eq!(u16_at(&bytes, end + 10), 2);
+		assert_eq!(u32_at(&bytes, end + 12), directory_size as u32);
+		assert_eq!(u32_at(&bytes, end + 16), local_size as u32);
+		// The second file's directory entry points at its local header
+		let second_entry = local_size + 46 + 5;
+		assert_eq!(u32_at(&bytes, second_entry + 42), 40);
+		assert_eq!(&bytes[second_entry + 46..second_entry + 51], b"b.txt");
+	}
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use prelude::*;
 
 #[allow(dead_code)]
@@ -19,6 +21,7 @@ pub mod prelude {
 	extern "C" {
 		// Use `js_namespace` here to bind `console.log(..)` instead of just
 		// `log(..)`
+		#[cfg(target_arch = "wasm32")]
 		#[wasm_bindgen(js_namespace = console)]
 		pub fn log(s: &str);
 
@@ -32,9 +35,30 @@ pub mod prelude {
 		#[wasm_bindgen(js_namespace = console, js_name = log)]
 		pub fn log_many(a: &str, b: &str);
 
+		#[cfg(target_arch = "wasm32")]
 		#[wasm_bindgen(js_namespace = console)]
 		pub fn error(s: &str);
 	}
+
+	// The console bindings panic outside of the browser, so native builds (i.e.
+	// `cargo test`) print to the terminal instead.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn log(s: &str) {
+		println!("{s}");
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[allow(dead_code)]
+	pub fn error(s: &str) {
+		eprintln!("{s}");
+	}
+}
+
+/// Reads a duration given in milliseconds by JavaScript, which could be any
+/// number at all, so it's checked rather than letting `Duration` panic on it.
+pub fn duration_from_millis(millis: f64) -> Result<Duration, String> {
+	Duration::try_from_secs_f64(millis / 1000.0)
+		.map_err(|_| format!("{millis} isn't a valid number of milliseconds"))
 }
 
 #[wasm_bindgen]
@@ -60,3 +84,19 @@ macro_rules! waves_error {
 
 #[allow(unused_imports)]
 pub(crate) use waves_error;
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use crate::utils::duration_from_millis;
+
+	#[test]
+	fn durations_from_javascript_are_checked() {
+		assert_eq!(duration_from_millis(16.5), Ok(Duration::from_micros(16_500)));
+		assert_eq!(duration_from_millis(0.0), Ok(Duration::ZERO));
+		for millis in [-1.0, f64::NAN, f64::INFINITY, f64::MAX] {
+			assert!(duration_from_millis(millis).is_err());
+		}
+	}
+}